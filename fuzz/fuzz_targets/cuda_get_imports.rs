@@ -0,0 +1,258 @@
+#![no_main]
+
+//! Fuzz `cuda_get_imports` with modules whose import section holds
+//! arbitrary, often malformed, import types: cuda names with the wrong
+//! kind or signature, unknown names in cuda namespaces, and the like.
+
+use libfuzzer_sys::{arbitrary, arbitrary::Arbitrary, fuzz_target};
+use std::ffi::CString;
+use wasmer_c_api::wasm_c_api::{
+    cuda::{
+        cuda_env_delete, cuda_env_deny_import, cuda_get_imports, cuda_imports_error_t,
+        mock::cuda_env_new_mock,
+    },
+    engine::wasm_engine_new,
+    externals::wasm_extern_vec_t,
+    module::wasm_module_new,
+    store::wasm_store_new,
+    types::wasm_byte_vec_t,
+};
+
+const NAMESPACES: &[&str] = &["cuda", "env", "nvtx", "wasi_snapshot_preview1"];
+
+const NAMES: &[&str] = &[
+    "cuInit",
+    "cuDeviceGet",
+    "cuCtxCreate",
+    "cuMemAlloc",
+    "cuMemFree",
+    "cuMemcpyHtoD",
+    "cuModuleLoadData",
+    "cuLaunchKernel",
+    "cuStreamCreate",
+    "cuEventRecord",
+    "cuda_error_name",
+    "cuda_error_string",
+    "cuGetLastErrorString",
+    "cuda_supported_functions",
+    "nvtxRangePushA",
+    "nvtxRangePush",
+    "nvtxRangePop",
+];
+
+#[derive(Arbitrary, Debug)]
+enum Name {
+    Known(u8),
+    Other(String),
+}
+
+impl Name {
+    fn resolve<'a>(&'a self, known: &[&'a str]) -> &'a str {
+        match self {
+            Name::Known(index) => known[*index as usize % known.len()],
+            Name::Other(name) => name,
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug, Clone, Copy)]
+enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+    V128,
+    FuncRef,
+    ExternRef,
+}
+
+impl ValType {
+    fn encode(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+            ValType::F32 => 0x7d,
+            ValType::F64 => 0x7c,
+            ValType::V128 => 0x7b,
+            ValType::FuncRef => 0x70,
+            ValType::ExternRef => 0x6f,
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum ImportType {
+    Function {
+        params: Vec<ValType>,
+        results: Vec<ValType>,
+    },
+    Table {
+        element: ValType,
+        minimum: u32,
+        maximum: Option<u32>,
+    },
+    Memory {
+        minimum: u32,
+        maximum: Option<u32>,
+    },
+    Global {
+        content: ValType,
+        mutable: bool,
+    },
+}
+
+#[derive(Arbitrary, Debug)]
+struct Import {
+    namespace: Name,
+    name: Name,
+    ty: ImportType,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    imports: Vec<Import>,
+    denied: Vec<Name>,
+}
+
+fn write_u32(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    write_u32(bytes, name.len() as u32);
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+fn write_limits(bytes: &mut Vec<u8>, minimum: u32, maximum: Option<u32>) {
+    match maximum {
+        Some(maximum) => {
+            bytes.push(0x01);
+            write_u32(bytes, minimum);
+            write_u32(bytes, maximum);
+        }
+        None => {
+            bytes.push(0x00);
+            write_u32(bytes, minimum);
+        }
+    }
+}
+
+fn write_section(bytes: &mut Vec<u8>, id: u8, content: &[u8]) {
+    bytes.push(id);
+    write_u32(bytes, content.len() as u32);
+    bytes.extend_from_slice(content);
+}
+
+/// a module holding only the type and import sections built from
+/// `imports`
+fn encode_module(imports: &[Import]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut entries = Vec::new();
+    let mut type_count = 0;
+
+    for import in imports {
+        write_name(&mut entries, import.namespace.resolve(NAMESPACES));
+        write_name(&mut entries, import.name.resolve(NAMES));
+
+        match &import.ty {
+            ImportType::Function { params, results } => {
+                types.push(0x60);
+                for valtypes in &[params, results] {
+                    write_u32(&mut types, valtypes.len() as u32);
+                    types.extend(valtypes.iter().map(|valtype| valtype.encode()));
+                }
+
+                entries.push(0x00);
+                write_u32(&mut entries, type_count);
+                type_count += 1;
+            }
+            ImportType::Table {
+                element,
+                minimum,
+                maximum,
+            } => {
+                entries.push(0x01);
+                entries.push(element.encode());
+                write_limits(&mut entries, *minimum, *maximum);
+            }
+            ImportType::Memory { minimum, maximum } => {
+                entries.push(0x02);
+                write_limits(&mut entries, *minimum, *maximum);
+            }
+            ImportType::Global { content, mutable } => {
+                entries.push(0x03);
+                entries.push(content.encode());
+                entries.push(*mutable as u8);
+            }
+        }
+    }
+
+    let mut type_section = Vec::new();
+    write_u32(&mut type_section, type_count);
+    type_section.extend(types);
+    let mut import_section = Vec::new();
+    write_u32(&mut import_section, imports.len() as u32);
+    import_section.extend(entries);
+
+    let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+    write_section(&mut bytes, 1, &type_section);
+    write_section(&mut bytes, 2, &import_section);
+
+    bytes
+}
+
+fuzz_target!(|input: Input| {
+    let wasm_bytes = encode_module(&input.imports);
+
+    if let Ok(path) = std::env::var("DUMP_TESTCASE") {
+        use std::fs::File;
+        use std::io::Write;
+        let mut file = File::create(path).unwrap();
+        file.write_all(&wasm_bytes).unwrap();
+        return;
+    }
+
+    let wasm_bytes: wasm_byte_vec_t = wasm_bytes.into();
+    let engine = wasm_engine_new();
+
+    unsafe {
+        let store = wasm_store_new(Some(&engine)).unwrap();
+        // the encoding can still be invalid, e.g. with several memories
+        let module = match wasm_module_new(Some(&store), Some(&wasm_bytes)) {
+            Some(module) => module,
+            None => return,
+        };
+        // a mock environment has the real cuda imports, without a GPU
+        let cuda_env = cuda_env_new_mock();
+        for name in &input.denied {
+            if let Ok(name) = CString::new(name.resolve(NAMES)) {
+                cuda_env_deny_import(&*cuda_env, name.as_ptr());
+            }
+        }
+        let mut imports: wasm_extern_vec_t = Vec::new().into();
+
+        // Unresolved and mistyped imports are reported through the
+        // last error, the harness only looks for panics, which are
+        // reported as `CUDA_IMPORTS_INTERNAL`, and memory errors.
+        let code = cuda_get_imports(
+            Some(&store),
+            Some(&module),
+            cuda_env.as_ref(),
+            Some(&mut imports),
+        );
+        assert_ne!(code, cuda_imports_error_t::CUDA_IMPORTS_INTERNAL);
+        if code == cuda_imports_error_t::CUDA_IMPORTS_OK {
+            assert_eq!(imports.as_slice().len(), input.imports.len());
+        }
+
+        cuda_env_delete(cuda_env);
+    }
+});
@@ -0,0 +1,78 @@
+#![no_main]
+
+use libfuzzer_sys::{arbitrary, arbitrary::Arbitrary, fuzz_target};
+use wasm_smith::{Config, ConfiguredModule};
+use wasmer_c_api::wasm_c_api::{
+    cuda::{cuda_env_delete, cuda_env_new, cuda_imports_error_t, wasi::cuda_wasi_get_imports},
+    engine::wasm_engine_new,
+    externals::wasm_extern_vec_t,
+    module::wasm_module_new,
+    store::wasm_store_new,
+    types::wasm_byte_vec_t,
+    wasi::{wasi_config_new, wasi_env_new},
+};
+
+#[derive(Arbitrary, Debug, Default, Copy, Clone)]
+struct ImportsConfig;
+impl Config for ImportsConfig {
+    fn max_imports(&self) -> usize {
+        100
+    }
+    fn max_memory_pages(&self) -> u32 {
+        // https://github.com/wasmerio/wasmer/issues/2187
+        65535
+    }
+    fn allow_start_export(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Arbitrary)]
+struct WasmSmithModule(ConfiguredModule<ImportsConfig>);
+impl std::fmt::Debug for WasmSmithModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&wasmprinter::print_bytes(self.0.to_bytes()).unwrap())
+    }
+}
+
+fuzz_target!(|module: WasmSmithModule| {
+    let wasm_bytes = module.0.to_bytes();
+
+    if let Ok(path) = std::env::var("DUMP_TESTCASE") {
+        use std::fs::File;
+        use std::io::Write;
+        let mut file = File::create(path).unwrap();
+        file.write_all(&wasm_bytes).unwrap();
+        return;
+    }
+
+    let wasm_bytes: wasm_byte_vec_t = wasm_bytes.into();
+    let engine = wasm_engine_new();
+
+    unsafe {
+        let store = wasm_store_new(Some(&engine)).unwrap();
+        let module = match wasm_module_new(Some(&store), Some(&wasm_bytes)) {
+            Some(module) => module,
+            None => return,
+        };
+        let wasi_config = wasi_config_new(b"fuzz\0".as_ptr() as _).unwrap();
+        let wasi_env = wasi_env_new(wasi_config).unwrap();
+        let cuda_env = cuda_env_new();
+        let mut imports: wasm_extern_vec_t = Vec::new().into();
+
+        // Missing WASI versions and unresolved imports are reported
+        // through the last error, the harness only looks for panics,
+        // which are reported as `CUDA_IMPORTS_INTERNAL`, and memory
+        // errors.
+        let code = cuda_wasi_get_imports(
+            Some(&store),
+            Some(&module),
+            cuda_env.as_ref(),
+            Some(&wasi_env),
+            Some(&mut imports),
+        );
+        assert_ne!(code, cuda_imports_error_t::CUDA_IMPORTS_INTERNAL);
+
+        cuda_env_delete(cuda_env);
+    }
+});
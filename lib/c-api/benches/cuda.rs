@@ -0,0 +1,141 @@
+//! Benchmarks of the CUDA imports of the C API, against a mock
+//! environment so that they run without a GPU: building the imports,
+//! instantiating a module with them, and the overhead of a call from
+//! the guest into the host functions the C API adds.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::convert::TryFrom;
+use wasmer::wasm_c_api::cuda::mock::cuda_env_new_mock;
+use wasmer::wasm_c_api::cuda::{cuda_env_delete, cuda_get_imports, cuda_imports_error_t};
+use wasmer::wasm_c_api::engine::{wasm_engine_new, wasm_engine_t};
+use wasmer::wasm_c_api::externals::{
+    wasm_extern_as_func, wasm_extern_vec_t, wasm_func_call, wasm_func_t,
+};
+use wasmer::wasm_c_api::instance::{wasm_instance_exports, wasm_instance_new, wasm_instance_t};
+use wasmer::wasm_c_api::module::{wasm_module_new, wasm_module_t};
+use wasmer::wasm_c_api::store::{wasm_store_new, wasm_store_t};
+use wasmer::wasm_c_api::types::wasm_byte_vec_t;
+use wasmer::wasm_c_api::value::{wasm_val_t, wasm_val_vec_t};
+use wasmer_api::{wat2wasm, Val};
+
+static CUDA_WAT: &str = r#"(module
+    (import "env" "cudaGetDeviceProperties_v2" (func $properties (param i32 i32) (result i32)))
+    (import "nvtx" "nvtxRangePushA" (func $push (param i32) (result i32)))
+    (import "nvtx" "nvtxRangePop" (func $pop (result i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 0) "bench\00")
+    (func (export "properties") (result i32)
+       (call $properties (i32.const 1024) (i32.const 0)))
+    (func (export "range") (result i32)
+       (drop (call $push (i32.const 0)))
+       (call $pop))
+)"#;
+
+struct Setup {
+    _engine: Box<wasm_engine_t>,
+    store: Box<wasm_store_t>,
+    module: Box<wasm_module_t>,
+}
+
+fn setup() -> Setup {
+    let engine = wasm_engine_new();
+    let store = unsafe { wasm_store_new(Some(&engine)) }.unwrap();
+    let wasm: wasm_byte_vec_t = wat2wasm(CUDA_WAT.as_bytes()).unwrap().into_owned().into();
+    let module = unsafe { wasm_module_new(Some(&store), Some(&wasm)) }.unwrap();
+
+    Setup {
+        _engine: engine,
+        store,
+        module,
+    }
+}
+
+/// the cuda imports of `setup.module`, for a new mock environment
+fn imports(setup: &Setup) -> wasm_extern_vec_t {
+    let cuda_env = cuda_env_new_mock();
+    let mut imports: wasm_extern_vec_t = Vec::new().into();
+
+    unsafe {
+        assert_eq!(
+            cuda_get_imports(
+                Some(&setup.store),
+                Some(&setup.module),
+                Some(&*cuda_env),
+                Some(&mut imports),
+            ),
+            cuda_imports_error_t::CUDA_IMPORTS_OK
+        );
+        cuda_env_delete(cuda_env);
+    }
+
+    imports
+}
+
+fn instance(setup: &Setup, imports: &wasm_extern_vec_t) -> Box<wasm_instance_t> {
+    unsafe { wasm_instance_new(Some(&setup.store), Some(&setup.module), Some(imports), None) }
+        .unwrap()
+}
+
+/// call `func`, without arguments, and return its `i32` result
+fn call(func: &wasm_func_t) -> i32 {
+    let args: wasm_val_vec_t = Vec::new().into();
+    let mut results: wasm_val_vec_t = vec![wasm_val_t::default()].into();
+
+    let trap = unsafe { wasm_func_call(Some(func), Some(&args), &mut results) };
+    assert!(trap.is_none());
+
+    match Val::try_from(&results.as_slice()[0]).unwrap() {
+        Val::I32(result) => result,
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+pub fn run_import_building(c: &mut Criterion) {
+    let setup = setup();
+
+    c.bench_function("cuda get imports (mock)", |b| {
+        b.iter(|| black_box(imports(&setup)))
+    });
+}
+
+pub fn run_instantiation(c: &mut Criterion) {
+    let setup = setup();
+    let imports = imports(&setup);
+
+    c.bench_function("cuda instance new (mock)", |b| {
+        b.iter(|| black_box(instance(&setup, &imports)))
+    });
+}
+
+pub fn run_host_calls(c: &mut Criterion) {
+    let setup = setup();
+    let imports = imports(&setup);
+    let instance = instance(&setup, &imports);
+    let mut exports: wasm_extern_vec_t = Vec::new().into();
+    unsafe { wasm_instance_exports(&instance, &mut exports) };
+    // in the order of the exports of `CUDA_WAT`, after the memory
+    let export = |index: usize| wasm_extern_as_func(exports.as_slice()[index].as_deref()).unwrap();
+    let (properties, range) = (export(1), export(2));
+
+    c.bench_function("cuda device properties call (mock)", |b| {
+        b.iter(|| {
+            let result = black_box(call(properties));
+            assert_eq!(result, 0);
+        })
+    });
+
+    // without a `libnvToolsExt`, this is the overhead of the host calls
+    // themselves
+    c.bench_function("cuda nvtx range call", |b| {
+        b.iter(|| black_box(call(range)))
+    });
+}
+
+criterion_group!(
+    benches,
+    run_import_building,
+    run_instantiation,
+    run_host_calls
+);
+
+criterion_main!(benches);
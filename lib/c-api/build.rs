@@ -49,6 +49,12 @@ const WASI_FEATURE_AS_C_DEFINE: &'static str = "WASMER_WASI_ENABLED";
 #[allow(unused)]
 const MIDDLEWARES_FEATURE_AS_C_DEFINE: &'static str = "WASMER_MIDDLEWARES_ENABLED";
 
+#[allow(unused)]
+const CUDA_FEATURE_AS_C_DEFINE: &'static str = "WASMER_CUDA_ENABLED";
+
+#[allow(unused)]
+const NVTX_FEATURE_AS_C_DEFINE: &'static str = "WASMER_NVTX_ENABLED";
+
 #[allow(unused)]
 const EMSCRIPTEN_FEATURE_AS_C_DEFINE: &'static str = "WASMER_EMSCRIPTEN_ENABLED";
 
@@ -144,6 +150,8 @@ fn build_wasm_c_api_headers(crate_dir: &str, out_dir: &str) {
     map_feature_as_c_define!("compiler", COMPILER_FEATURE_AS_C_DEFINE, pre_header);
     map_feature_as_c_define!("wasi", WASI_FEATURE_AS_C_DEFINE, pre_header);
     map_feature_as_c_define!("middlewares", MIDDLEWARES_FEATURE_AS_C_DEFINE, pre_header);
+    map_feature_as_c_define!("cuda", CUDA_FEATURE_AS_C_DEFINE, pre_header);
+    map_feature_as_c_define!("nvtx", NVTX_FEATURE_AS_C_DEFINE, pre_header);
     map_feature_as_c_define!("emscripten", EMSCRIPTEN_FEATURE_AS_C_DEFINE, pre_header);
 
     add_wasmer_version(&mut pre_header);
@@ -219,6 +227,8 @@ fn new_builder(language: Language, crate_dir: &str, include_guard: &str, header:
         .with_define("feature", "universal", UNIVERSAL_FEATURE_AS_C_DEFINE)
         .with_define("feature", "compiler", COMPILER_FEATURE_AS_C_DEFINE)
         .with_define("feature", "wasi", WASI_FEATURE_AS_C_DEFINE)
+        .with_define("feature", "cuda", CUDA_FEATURE_AS_C_DEFINE)
+        .with_define("feature", "nvtx", NVTX_FEATURE_AS_C_DEFINE)
         .with_define("feature", "emscripten", EMSCRIPTEN_FEATURE_AS_C_DEFINE);
 
     builder
@@ -0,0 +1,64 @@
+// C++ RAII helpers for the CUDA part of the Wasmer C API.
+//
+// This file is maintained by hand, and installed next to the
+// `wasmer.h` header generated by `lib/c-api/build.rs`.
+
+#if !defined(WASMER_CUDA_HPP)
+#define WASMER_CUDA_HPP
+
+#include "wasmer.h"
+
+// Owns a `cuda_env_t*` and deletes it with `cuda_env_delete` when it
+// goes out of scope.
+class CudaEnvGuard {
+public:
+    CudaEnvGuard() : env_(cuda_env_new()) {}
+    explicit CudaEnvGuard(cuda_env_t *env) : env_(env) {}
+    ~CudaEnvGuard() { cuda_env_delete(env_); }
+
+    CudaEnvGuard(const CudaEnvGuard &) = delete;
+    CudaEnvGuard &operator=(const CudaEnvGuard &) = delete;
+
+    CudaEnvGuard(CudaEnvGuard &&other) noexcept : env_(other.release()) {}
+    CudaEnvGuard &operator=(CudaEnvGuard &&other) noexcept {
+        if (this != &other) {
+            cuda_env_delete(env_);
+            env_ = other.release();
+        }
+        return *this;
+    }
+
+    cuda_env_t *get() const { return env_; }
+
+    // Give up the ownership, the caller must delete the env.
+    cuda_env_t *release() {
+        cuda_env_t *env = env_;
+        env_ = nullptr;
+        return env;
+    }
+
+    explicit operator bool() const { return env_ != nullptr; }
+
+private:
+    cuda_env_t *env_;
+};
+
+// Owns a `wasm_extern_vec_t`, e.g. the imports filled by
+// `cuda_get_imports`, and deletes it with `wasm_extern_vec_delete`
+// when it goes out of scope.
+class WasmExternVecGuard {
+public:
+    WasmExternVecGuard() { wasm_extern_vec_new_empty(&vec_); }
+    ~WasmExternVecGuard() { wasm_extern_vec_delete(&vec_); }
+
+    WasmExternVecGuard(const WasmExternVecGuard &) = delete;
+    WasmExternVecGuard &operator=(const WasmExternVecGuard &) = delete;
+
+    wasm_extern_vec_t *get() { return &vec_; }
+    const wasm_extern_vec_t *get() const { return &vec_; }
+
+private:
+    wasm_extern_vec_t vec_;
+};
+
+#endif // WASMER_CUDA_HPP
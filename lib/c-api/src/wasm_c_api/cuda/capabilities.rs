@@ -0,0 +1,156 @@
+//! Capability groups of the cuda imports, so that an embedder can
+//! restrict what an untrusted guest may do with the GPU, e.g. only
+//! copy memory, without loading modules or launching kernels.
+//!
+//! The cuda imports outside the capabilities of a `cuda_env_t` are
+//! still registered, so that modules declaring them still resolve,
+//! but they fail with `CUDA_ERROR_NOT_PERMITTED` (800) when called,
+//! which is recorded as the last error of the environment.
+
+use super::{cuda_env_t, error_stub, CudaEnvShared, CUDA_ERROR_NOT_PERMITTED};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use wasmer_api::{Extern, Store};
+
+/// Capability groups of the cuda imports, to be combined into the
+/// mask given to `cuda_env_set_capabilities`.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum cuda_capability_t {
+    /// Initialization, device, context and version queries, and
+    /// every cuda import that isn't in another group.
+    CUDA_CAPABILITY_DEVICE_QUERY = 1,
+
+    /// Allocating and freeing device and host memory.
+    CUDA_CAPABILITY_MEMORY = 2,
+
+    /// Copying and setting memory.
+    CUDA_CAPABILITY_TRANSFER = 4,
+
+    /// Loading modules and looking up their functions and globals.
+    CUDA_CAPABILITY_MODULE = 8,
+
+    /// Configuring and launching kernels.
+    CUDA_CAPABILITY_LAUNCH = 16,
+
+    /// Creating, synchronizing and destroying streams.
+    CUDA_CAPABILITY_STREAMS = 32,
+
+    /// Creating, recording and destroying events.
+    CUDA_CAPABILITY_EVENTS = 64,
+}
+
+/// every capability, the default of a `cuda_env_t`
+pub(super) const CUDA_CAPABILITIES_ALL: u32 = 0x7f;
+
+/// the group of the imports starting with each prefix, the first
+/// matching prefix wins
+const CAPABILITY_PREFIXES: &[(&str, cuda_capability_t)] = &[
+    ("cuMemcpy", cuda_capability_t::CUDA_CAPABILITY_TRANSFER),
+    ("cudaMemcpy", cuda_capability_t::CUDA_CAPABILITY_TRANSFER),
+    ("cuMemset", cuda_capability_t::CUDA_CAPABILITY_TRANSFER),
+    ("cudaMemset", cuda_capability_t::CUDA_CAPABILITY_TRANSFER),
+    ("cuMem", cuda_capability_t::CUDA_CAPABILITY_MEMORY),
+    ("cudaMem", cuda_capability_t::CUDA_CAPABILITY_MEMORY),
+    ("cudaMalloc", cuda_capability_t::CUDA_CAPABILITY_MEMORY),
+    ("cudaFree", cuda_capability_t::CUDA_CAPABILITY_MEMORY),
+    ("cudaHost", cuda_capability_t::CUDA_CAPABILITY_MEMORY),
+    ("cuModule", cuda_capability_t::CUDA_CAPABILITY_MODULE),
+    ("cuLink", cuda_capability_t::CUDA_CAPABILITY_MODULE),
+    ("cuLaunch", cuda_capability_t::CUDA_CAPABILITY_LAUNCH),
+    ("cudaLaunch", cuda_capability_t::CUDA_CAPABILITY_LAUNCH),
+    ("cuFunc", cuda_capability_t::CUDA_CAPABILITY_LAUNCH),
+    ("cudaFunc", cuda_capability_t::CUDA_CAPABILITY_LAUNCH),
+    (
+        "cudaConfigureCall",
+        cuda_capability_t::CUDA_CAPABILITY_LAUNCH,
+    ),
+    ("cuStream", cuda_capability_t::CUDA_CAPABILITY_STREAMS),
+    ("cudaStream", cuda_capability_t::CUDA_CAPABILITY_STREAMS),
+    ("cuEvent", cuda_capability_t::CUDA_CAPABILITY_EVENTS),
+    ("cudaEvent", cuda_capability_t::CUDA_CAPABILITY_EVENTS),
+];
+
+/// the capability group of the cuda import `name`
+pub(super) fn capability_of(name: &str) -> cuda_capability_t {
+    CAPABILITY_PREFIXES
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, capability)| *capability)
+        .unwrap_or(cuda_capability_t::CUDA_CAPABILITY_DEVICE_QUERY)
+}
+
+/// Restrict the cuda imports of `cuda_env` to the capability groups of
+/// `mask`, a combination of `cuda_capability_t`.
+///
+/// By default every capability is enabled. The cuda imports outside
+/// `mask` are still registered by the next calls to `cuda_get_imports`
+/// and the like, so that modules declaring them still resolve, but
+/// they return `CUDA_ERROR_NOT_PERMITTED` (800) when called, and
+/// record it as the last error of `cuda_env`, see
+/// `cuda_env_last_error`. Imports built before the call aren't
+/// affected.
+#[no_mangle]
+pub extern "C" fn cuda_env_set_capabilities(cuda_env: &cuda_env_t, mask: u32) {
+    if mask & !CUDA_CAPABILITIES_ALL != 0 {
+        log::warn!(
+            "cuda_env_set_capabilities: unknown capabilities in {:#x}",
+            mask
+        );
+    }
+
+    cuda_env
+        .shared
+        .capabilities
+        .store(mask & CUDA_CAPABILITIES_ALL, Ordering::SeqCst);
+}
+
+/// Get the capability group of the cuda import `name`, e.g. to audit
+/// what a module can do under a given mask.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_import_capability(name: *const c_char) -> cuda_capability_t {
+    debug_assert!(!name.is_null());
+
+    capability_of(&CStr::from_ptr(name).to_string_lossy())
+}
+
+/// replace the functions of `externs` outside the capabilities of
+/// `shared` by stubs failing with `CUDA_ERROR_NOT_PERMITTED`
+pub(super) fn mask_externs(
+    store: &Store,
+    shared: &Arc<CudaEnvShared>,
+    externs: &mut Vec<(String, String, Extern)>,
+) {
+    let mask = shared.capabilities.load(Ordering::SeqCst);
+    if mask == CUDA_CAPABILITIES_ALL {
+        return;
+    }
+
+    let mut masked = 0;
+    for (namespace, name, extern_) in externs.iter_mut() {
+        if mask & capability_of(name) as u32 != 0 {
+            continue;
+        }
+
+        if let Extern::Function(function) = extern_ {
+            let stub = error_stub(
+                store,
+                function.ty().clone(),
+                format!("{}::{}", namespace, name),
+                CUDA_ERROR_NOT_PERMITTED,
+                shared.clone(),
+            );
+            *extern_ = Extern::from(stub);
+            masked += 1;
+        }
+    }
+
+    log::debug!(
+        "{} cuda imports are outside the capabilities {:#x}",
+        masked,
+        mask
+    );
+}
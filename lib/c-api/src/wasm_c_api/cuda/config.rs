@@ -0,0 +1,242 @@
+//! `cuda_env_config_t`, to describe a CUDA environment up front and
+//! create it in one call with `cuda_env_new_from_config`, like
+//! `wasm_config_t` with `wasm_engine_new_with_config`.
+//!
+//! Each setter has the same meaning as the function of the same name
+//! on a `cuda_env_t`, e.g. `cuda_env_config_allow_import` and
+//! `cuda_env_allow_import`, so the environment is the same as one
+//! created with `cuda_env_new` or `cuda_env_new_mock` and configured
+//! afterwards.
+
+use super::capabilities::CUDA_CAPABILITIES_ALL;
+use super::device::compute_capability;
+use super::{cuda_env_t, CudaEnvShared};
+use crate::error::update_last_error;
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// The configuration of a CUDA environment, see
+/// `cuda_env_new_from_config`.
+#[allow(non_camel_case_types)]
+pub struct cuda_env_config_t {
+    allowed_imports: HashSet<String>,
+    denied_imports: HashSet<String>,
+    capabilities: u32,
+    mock: bool,
+    /// 0 for no limit
+    memory_limit: u64,
+    /// `(major, minor)`
+    min_compute_capability: Option<(i32, i32)>,
+}
+
+impl Default for cuda_env_config_t {
+    fn default() -> Self {
+        Self {
+            allowed_imports: HashSet::new(),
+            denied_imports: HashSet::new(),
+            capabilities: CUDA_CAPABILITIES_ALL,
+            mock: false,
+            memory_limit: 0,
+            min_compute_capability: None,
+        }
+    }
+}
+
+/// Create a new default configuration: a real environment, with every
+/// cuda import and every capability.
+///
+/// It is consumed by `cuda_env_new_from_config`, or must be deleted
+/// with `cuda_env_config_delete`.
+#[no_mangle]
+pub extern "C" fn cuda_env_config_new() -> Box<cuda_env_config_t> {
+    Box::new(cuda_env_config_t::default())
+}
+
+/// Delete a configuration that wasn't passed to
+/// `cuda_env_new_from_config`.
+#[no_mangle]
+pub extern "C" fn cuda_env_config_delete(_config: Option<Box<cuda_env_config_t>>) {}
+
+/// Like `cuda_env_allow_import`.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_env_config_allow_import(
+    config: &mut cuda_env_config_t,
+    name: *const c_char,
+) -> bool {
+    debug_assert!(!name.is_null());
+
+    let name = c_try!(CStr::from_ptr(name).to_str(); otherwise false);
+    config.allowed_imports.insert(name.to_string());
+
+    true
+}
+
+/// Like `cuda_env_deny_import`.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_env_config_deny_import(
+    config: &mut cuda_env_config_t,
+    name: *const c_char,
+) -> bool {
+    debug_assert!(!name.is_null());
+
+    let name = c_try!(CStr::from_ptr(name).to_str(); otherwise false);
+    config.denied_imports.insert(name.to_string());
+
+    true
+}
+
+/// Like `cuda_env_set_capabilities`.
+#[no_mangle]
+pub extern "C" fn cuda_env_config_set_capabilities(config: &mut cuda_env_config_t, mask: u32) {
+    if mask & !CUDA_CAPABILITIES_ALL != 0 {
+        log::warn!(
+            "cuda_env_config_set_capabilities: unknown capabilities in {:#x}",
+            mask
+        );
+    }
+
+    config.capabilities = mask & CUDA_CAPABILITIES_ALL;
+}
+
+/// Create a mock environment, like `cuda_env_new_mock`, if `mock` is
+/// true.
+#[no_mangle]
+pub extern "C" fn cuda_env_config_set_mock(config: &mut cuda_env_config_t, mock: bool) {
+    config.mock = mock;
+}
+
+/// Like `cuda_env_set_memory_limit`.
+#[no_mangle]
+pub extern "C" fn cuda_env_config_set_memory_limit(config: &mut cuda_env_config_t, bytes: u64) {
+    config.memory_limit = bytes;
+}
+
+/// Require a compute capability of at least `major.minor` from the
+/// first device, the default device of the CUDA runtime, see
+/// `cuda_env_new_from_config`.
+#[no_mangle]
+pub extern "C" fn cuda_env_config_set_min_compute_capability(
+    config: &mut cuda_env_config_t,
+    major: i32,
+    minor: i32,
+) {
+    config.min_compute_capability = Some((major, minor));
+}
+
+/// Create a new CUDA environment from `config`, which is consumed.
+///
+/// Returns `NULL`, and sets the last error, if `config` requires a
+/// minimal compute capability, see
+/// `cuda_env_config_set_min_compute_capability`, and the first device
+/// doesn't have it or can't be queried, so that an embedder can
+/// refuse to run on an older architecture before instantiating
+/// anything.
+///
+/// The returned handle must be deleted with `cuda_env_delete`.
+#[no_mangle]
+pub extern "C" fn cuda_env_new_from_config(
+    config: Option<Box<cuda_env_config_t>>,
+) -> *mut cuda_env_t {
+    let config = match config {
+        Some(config) => config,
+        None => return ptr::null_mut(),
+    };
+
+    let shared = CudaEnvShared::new(if config.mock {
+        Some(Arc::default())
+    } else {
+        None
+    });
+    *shared.allowed_imports.write().unwrap() = config.allowed_imports;
+    *shared.denied_imports.write().unwrap() = config.denied_imports;
+    shared
+        .capabilities
+        .store(config.capabilities, Ordering::SeqCst);
+    *shared.memory_limit.lock().unwrap() = match config.memory_limit {
+        0 => None,
+        bytes => Some(bytes),
+    };
+
+    if let Some((min_major, min_minor)) = config.min_compute_capability {
+        match compute_capability(&shared, 0) {
+            Ok(capability) if capability >= (min_major, min_minor) => {}
+            Ok((major, minor)) => {
+                update_last_error(format!(
+                    "the compute capability {}.{} of device 0 is below the required {}.{}",
+                    major, minor, min_major, min_minor
+                ));
+                return ptr::null_mut();
+            }
+            Err(error) => {
+                update_last_error(error);
+                return ptr::null_mut();
+            }
+        }
+    }
+
+    cuda_env_t {
+        shared: Arc::new(shared),
+    }
+    .into_handle()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_cuda_env_new_from_config() {
+        use super::super::capabilities::cuda_capability_t;
+        use super::super::cuda_env_delete;
+        use super::*;
+        use crate::error::take_last_error;
+
+        let mut config = cuda_env_config_new();
+        unsafe {
+            assert!(cuda_env_config_allow_import(
+                &mut config,
+                b"cuDeviceTotalMem\0".as_ptr() as *const c_char
+            ));
+            assert!(cuda_env_config_deny_import(
+                &mut config,
+                b"cuMemGetInfo\0".as_ptr() as *const c_char
+            ));
+        }
+        cuda_env_config_set_capabilities(
+            &mut config,
+            cuda_capability_t::CUDA_CAPABILITY_MEMORY as u32,
+        );
+        cuda_env_config_set_mock(&mut config, true);
+        cuda_env_config_set_memory_limit(&mut config, 4 << 30);
+        cuda_env_config_set_min_compute_capability(&mut config, 7, 5);
+
+        let handle = cuda_env_new_from_config(Some(config));
+        assert!(!handle.is_null());
+        let cuda_env = unsafe { &*handle };
+        assert!(cuda_env.shared.mock.is_some());
+        assert!(cuda_env.is_import_enabled("cuDeviceTotalMem"));
+        assert!(!cuda_env.is_import_enabled("cuMemGetInfo"));
+        assert!(!cuda_env.is_import_enabled("cuDeviceComputeCapability"));
+        assert_eq!(
+            cuda_env.shared.capabilities.load(Ordering::SeqCst),
+            cuda_capability_t::CUDA_CAPABILITY_MEMORY as u32
+        );
+        assert_eq!(*cuda_env.shared.memory_limit.lock().unwrap(), Some(4 << 30));
+        unsafe { cuda_env_delete(handle) };
+
+        // the mock device is an 8.0 one
+        let mut config = cuda_env_config_new();
+        cuda_env_config_set_mock(&mut config, true);
+        cuda_env_config_set_min_compute_capability(&mut config, 9, 0);
+        assert!(cuda_env_new_from_config(Some(config)).is_null());
+        assert_eq!(
+            take_last_error().unwrap(),
+            "the compute capability 8.0 of device 0 is below the required 9.0"
+        );
+
+        assert!(cuda_env_new_from_config(None).is_null());
+        cuda_env_config_delete(Some(cuda_env_config_new()));
+    }
+}
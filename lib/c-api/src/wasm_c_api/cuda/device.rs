@@ -0,0 +1,864 @@
+//! Device queries for the guests, e.g. `cuDeviceTotalMem` or
+//! `cuMemGetInfo`, answered
+//! by the CUDA driver, see the `driver` module, or by the mock device
+//! of the environments made by `cuda_env_new_mock`.
+//!
+//! They are registered in every namespace holding cuda imports, unless
+//! the namespace already provides them, and like the cuda imports they
+//! follow the allow list, the deny list and the capabilities of the
+//! `cuda_env_t`. They return a `CUresult`, recorded as the last error
+//! of the environment when it isn't `CUDA_SUCCESS`.
+//!
+//! `cudaGetDeviceProperties_v2` is also registered in the `"env"`
+//! namespace, where the guests compiled from CUDA C++ import the CUDA
+//! runtime from.
+//!
+//! The memory they report can be limited per environment, see
+//! `cuda_env_set_memory_limit`, and an embedder can check the
+//! half-precision support of the device with
+//! `cuda_env_supports_half_precision`.
+
+use super::driver::{
+    initialized_driver, CUDA_ERROR_INVALID_DEVICE, CUDA_SUCCESS, CU_DEVICE_ATTRIBUTE_CLOCK_RATE,
+    CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR, CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR,
+    CU_DEVICE_ATTRIBUTE_GPU_OVERLAP, CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_X,
+    CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Y, CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Z,
+    CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_X, CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Y,
+    CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Z, CU_DEVICE_ATTRIBUTE_MAX_PITCH,
+    CU_DEVICE_ATTRIBUTE_MAX_REGISTERS_PER_BLOCK, CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK,
+    CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK, CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT,
+    CU_DEVICE_ATTRIBUTE_TEXTURE_ALIGNMENT, CU_DEVICE_ATTRIBUTE_TEXTURE_PITCH_ALIGNMENT,
+    CU_DEVICE_ATTRIBUTE_TOTAL_CONSTANT_MEMORY, CU_DEVICE_ATTRIBUTE_WARP_SIZE,
+};
+use super::{add_to_each_namespace, capabilities, cuda_env_t, CudaEnvShared, CudaError};
+use crate::error::update_last_error;
+use std::sync::Arc;
+use wasmer_api::{
+    Array, Extern, Function, HostEnvInitError, Instance, LazyInit, Memory, Store, WasmPtr,
+    WasmerEnv,
+};
+
+pub(super) const DEVICE_TOTAL_MEM_NAME: &str = "cuDeviceTotalMem";
+pub(super) const COMPUTE_CAPABILITY_NAME: &str = "cuDeviceComputeCapability";
+pub(super) const MEM_GET_INFO_NAME: &str = "cuMemGetInfo";
+pub(super) const DEVICE_PROPERTIES_NAME: &str = "cudaGetDeviceProperties_v2";
+
+/// the namespace of the CUDA runtime imports of the guests compiled
+/// from CUDA C++, see `add_runtime_functions`
+pub(super) const RUNTIME_NAMESPACE: &str = "env";
+
+/// the device queries, see `add_device_functions`
+pub(super) const DEVICE_IMPORTS: &[&str] = &[
+    DEVICE_TOTAL_MEM_NAME,
+    COMPUTE_CAPABILITY_NAME,
+    MEM_GET_INFO_NAME,
+    DEVICE_PROPERTIES_NAME,
+];
+
+/// A field of `cudaDeviceProp`.
+enum PropField {
+    /// fields that aren't device attributes, e.g. `uuid`, left to zero
+    Zeros(usize),
+    /// `totalGlobalMem`
+    TotalMem,
+    /// a device attribute, stored in an `int`
+    Int(i32),
+    /// a device attribute, stored in a `size_t`
+    SizeT(i32),
+}
+
+/// the fields of `cudaDeviceProp` after `name[256]`, up to
+/// `multiProcessorCount`, with the layout of wasm32, where `size_t` is
+/// 32 bits and saturates at 4 GiB - 1
+const DEVICE_PROP_FIELDS: &[PropField] = &[
+    // uuid, luid[8], luidDeviceNodeMask
+    PropField::Zeros(16 + 8 + 4),
+    PropField::TotalMem,
+    PropField::SizeT(CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_MAX_REGISTERS_PER_BLOCK),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_WARP_SIZE),
+    PropField::SizeT(CU_DEVICE_ATTRIBUTE_MAX_PITCH),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_X),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Y),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Z),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_X),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Y),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Z),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_CLOCK_RATE),
+    PropField::SizeT(CU_DEVICE_ATTRIBUTE_TOTAL_CONSTANT_MEMORY),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR),
+    PropField::SizeT(CU_DEVICE_ATTRIBUTE_TEXTURE_ALIGNMENT),
+    PropField::SizeT(CU_DEVICE_ATTRIBUTE_TEXTURE_PITCH_ALIGNMENT),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_GPU_OVERLAP),
+    PropField::Int(CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT),
+];
+
+/// the size of the start of `cudaDeviceProp` written by
+/// `cudaGetDeviceProperties_v2`
+const DEVICE_PROP_SIZE: usize = 364;
+
+#[derive(Clone)]
+struct DeviceEnv {
+    shared: Arc<CudaEnvShared>,
+    memory: LazyInit<Memory>,
+}
+
+impl WasmerEnv for DeviceEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        let memory: Memory = instance.exports.get_with_generics_weak("memory")?;
+        self.memory.initialize(memory);
+        Ok(())
+    }
+}
+
+/// check that `device` is a valid device ordinal
+fn check_device(shared: &CudaEnvShared, device: i32) -> Result<(), CudaError> {
+    let count = match &shared.mock {
+        Some(mock) => mock.device_count(),
+        None => initialized_driver()?.device_count()?,
+    };
+
+    if device < 0 || device >= count {
+        return Err(CudaError::Driver(CUDA_ERROR_INVALID_DEVICE));
+    }
+
+    Ok(())
+}
+
+/// the total memory of `device`, in bytes, or the memory limit of
+/// `shared` if there is one
+fn device_total_mem(shared: &CudaEnvShared, device: i32) -> Result<u64, CudaError> {
+    let total_mem = physical_total_mem(shared, device)?;
+
+    Ok(shared.memory_limit.lock().unwrap().unwrap_or(total_mem))
+}
+
+/// the total memory of `device`, in bytes, cached for the real devices
+fn physical_total_mem(shared: &CudaEnvShared, device: i32) -> Result<u64, CudaError> {
+    check_device(shared, device)?;
+
+    if let Some(mock) = &shared.mock {
+        return Ok(mock.device().total_mem);
+    }
+    if let Some(total_mem) = shared.device_total_mem.lock().unwrap().get(&device) {
+        return Ok(*total_mem);
+    }
+
+    let total_mem = initialized_driver()?.device_total_mem(device)?;
+    shared
+        .device_total_mem
+        .lock()
+        .unwrap()
+        .insert(device, total_mem);
+
+    Ok(total_mem)
+}
+
+/// the device of the current context, always the first one for the
+/// mock environments
+pub(super) fn current_device(shared: &CudaEnvShared) -> Result<i32, CudaError> {
+    match &shared.mock {
+        Some(_) => Ok(0),
+        None => initialized_driver()?.current_device(),
+    }
+}
+
+/// the `(major, minor)` compute capability of `device`, cached for
+/// the real devices
+pub(super) fn compute_capability(
+    shared: &CudaEnvShared,
+    device: i32,
+) -> Result<(i32, i32), CudaError> {
+    check_device(shared, device)?;
+
+    if let Some(mock) = &shared.mock {
+        return Ok(mock.device().compute_capability);
+    }
+    if let Some(capability) = shared.compute_capabilities.lock().unwrap().get(&device) {
+        return Ok(*capability);
+    }
+
+    let driver = initialized_driver()?;
+    let capability = (
+        driver.device_attribute(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR, device)?,
+        driver.device_attribute(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR, device)?,
+    );
+    shared
+        .compute_capabilities
+        .lock()
+        .unwrap()
+        .insert(device, capability);
+
+    Ok(capability)
+}
+
+/// the `(free, total)` memory of the device of the current context,
+/// in bytes, clamped to the memory limit of `shared` if there is one
+fn mem_info(shared: &CudaEnvShared) -> Result<(u64, u64), CudaError> {
+    let (free, total) = match &shared.mock {
+        Some(mock) => {
+            let device = mock.device();
+            let free = device.free_mem.unwrap_or(device.total_mem);
+
+            (free.min(device.total_mem), device.total_mem)
+        }
+        None => initialized_driver()?.mem_info()?,
+    };
+
+    match *shared.memory_limit.lock().unwrap() {
+        Some(limit) => Ok((free.min(limit.saturating_sub(memory_usage(shared))), limit)),
+        None => Ok((free, total)),
+    }
+}
+
+/// the device memory allocated by the guests of `shared`, in bytes.
+/// `wasmer-cuda` doesn't report its allocations, so it is only known
+/// for the mock environments.
+fn memory_usage(shared: &CudaEnvShared) -> u64 {
+    shared
+        .mock
+        .as_ref()
+        .map_or(0, |mock| mock.device().used_mem)
+}
+
+/// the `CUdevice_attribute` `attribute` of `device`
+pub(super) fn device_attribute(
+    shared: &CudaEnvShared,
+    attribute: i32,
+    device: i32,
+) -> Result<i32, CudaError> {
+    match &shared.mock {
+        Some(mock) => Ok(mock.device().attribute(attribute)),
+        None => initialized_driver()?.device_attribute(attribute, device),
+    }
+}
+
+/// the start of the `cudaDeviceProp` of `device`, see
+/// `DEVICE_PROP_FIELDS`
+fn device_prop(shared: &CudaEnvShared, device: i32) -> Result<Vec<u8>, CudaError> {
+    check_device(shared, device)?;
+
+    let name = match &shared.mock {
+        Some(mock) => mock.device().name,
+        None => initialized_driver()?.device_name(device)?,
+    };
+    let mut prop = name.into_bytes();
+    // nul-terminated
+    prop.truncate(255);
+    prop.resize(256, 0);
+
+    let size_t = |value: u64| (value.min(u32::MAX as u64) as u32).to_le_bytes();
+    for field in DEVICE_PROP_FIELDS {
+        match *field {
+            PropField::Zeros(size) => prop.resize(prop.len() + size, 0),
+            PropField::TotalMem => {
+                let total_mem = device_total_mem(shared, device)?;
+                if total_mem > u32::MAX as u64 {
+                    log::warn!(
+                        "{}: the {} bytes of device {} don't fit in the 32-bit totalGlobalMem, \
+                         4 GiB - 1 is reported instead, use cuDeviceTotalMem",
+                        DEVICE_PROPERTIES_NAME,
+                        total_mem,
+                        device,
+                    );
+                }
+                prop.extend_from_slice(&size_t(total_mem));
+            }
+            PropField::Int(attribute) => {
+                let value = device_attribute(shared, attribute, device)?;
+                prop.extend_from_slice(&value.to_le_bytes());
+            }
+            PropField::SizeT(attribute) => {
+                let value = device_attribute(shared, attribute, device)?;
+                prop.extend_from_slice(&size_t(value.max(0) as u64));
+            }
+        }
+    }
+    debug_assert_eq!(prop.len(), DEVICE_PROP_SIZE);
+
+    Ok(prop)
+}
+
+/// write each little-endian value of `values` at its pointer, once
+/// all of them are known to be in bounds, so that nothing is written
+/// on failure
+fn write_values(
+    memory: &LazyInit<Memory>,
+    values: &[(WasmPtr<u8, Array>, &[u8])],
+) -> Result<(), CudaError> {
+    let cells = values
+        .iter()
+        .map(|(ptr, bytes)| {
+            memory
+                .get_ref()
+                .and_then(|memory| ptr.deref(memory, 0, bytes.len() as u32))
+                .ok_or(CudaError::Bounds {
+                    offset: ptr.offset() as u64,
+                    len: bytes.len() as u64,
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (cells, (_, bytes)) in cells.iter().zip(values) {
+        cells
+            .iter()
+            .zip(bytes.iter())
+            .for_each(|(cell, byte)| cell.set(*byte));
+    }
+
+    Ok(())
+}
+
+/// turn `result` into the `CUresult` returned to the guest, recording
+/// it as the last error of `shared` on failure
+fn cu_result(shared: &CudaEnvShared, call: &str, result: Result<(), CudaError>) -> i32 {
+    match result {
+        Ok(()) => CUDA_SUCCESS,
+        Err(error) => {
+            log::debug!("{}: {}", call, error);
+            let code = error.cu_result();
+            shared.record_error(call, code);
+            code
+        }
+    }
+}
+
+/// Write the total memory of `device`, in bytes, as a `u64` at
+/// `bytes`.
+fn cu_device_total_mem(env: &DeviceEnv, bytes: WasmPtr<u8, Array>, device: i32) -> i32 {
+    let result = device_total_mem(&env.shared, device)
+        .and_then(|total_mem| write_values(&env.memory, &[(bytes, &total_mem.to_le_bytes())]));
+
+    cu_result(&env.shared, DEVICE_TOTAL_MEM_NAME, result)
+}
+
+/// Write the compute capability of `device` as two `i32`, at `major`
+/// and at `minor`, so that a guest can select the right PTX or refuse
+/// to run on an older architecture.
+fn cu_device_compute_capability(
+    env: &DeviceEnv,
+    major: WasmPtr<u8, Array>,
+    minor: WasmPtr<u8, Array>,
+    device: i32,
+) -> i32 {
+    let result = compute_capability(&env.shared, device).and_then(|(major_value, minor_value)| {
+        write_values(
+            &env.memory,
+            &[
+                (major, &major_value.to_le_bytes()),
+                (minor, &minor_value.to_le_bytes()),
+            ],
+        )
+    });
+
+    cu_result(&env.shared, COMPUTE_CAPABILITY_NAME, result)
+}
+
+/// Write the free and the total memory of the device of the current
+/// context, in bytes, as two `u64`, at `free` and at `total`, so that a
+/// guest can size its buffers.
+///
+/// Like the native `cuMemGetInfo`, it needs a current context on the
+/// calling thread, e.g. after `cuCtxCreate`: without one, the driver
+/// returns `CUDA_ERROR_INVALID_CONTEXT` (201), which is returned to
+/// the guest and recorded as the last error.
+fn cu_mem_get_info(env: &DeviceEnv, free: WasmPtr<u8, Array>, total: WasmPtr<u8, Array>) -> i32 {
+    let result = mem_info(&env.shared).and_then(|(free_value, total_value)| {
+        write_values(
+            &env.memory,
+            &[
+                (free, &free_value.to_le_bytes()),
+                (total, &total_value.to_le_bytes()),
+            ],
+        )
+    });
+
+    cu_result(&env.shared, MEM_GET_INFO_NAME, result)
+}
+
+/// Write the start of the `cudaDeviceProp` of `device` at `prop`, up to
+/// `multiProcessorCount`, for the guests compiled from CUDA C++. The
+/// rest of the struct is left untouched.
+///
+/// The `size_t` fields are 32 bits on wasm32, so they saturate:
+/// `totalGlobalMem` is 4 GiB - 1 for the devices with more memory,
+/// which is logged as a warning. `cuDeviceTotalMem` gives the exact
+/// value as a `u64`.
+///
+/// Returns a `cudaError_t`, whose codes are the same as the `CUresult`
+/// ones here.
+fn cuda_get_device_properties(env: &DeviceEnv, prop: WasmPtr<u8, Array>, device: i32) -> i32 {
+    let result = device_prop(&env.shared, device)
+        .and_then(|device_prop| write_values(&env.memory, &[(prop, &device_prop)]));
+
+    cu_result(&env.shared, DEVICE_PROPERTIES_NAME, result)
+}
+
+/// Limit the device memory of the guests of `cuda_env` to `bytes`, or
+/// remove the limit if `bytes` is 0.
+///
+/// With a limit, `cuMemGetInfo` reports the limit as the total memory,
+/// and as the free memory at most the limit minus the memory the
+/// guests of `cuda_env` allocated, while `cuDeviceTotalMem` and
+/// `cudaGetDeviceProperties_v2` report the limit, so that the guests
+/// can neither learn the capacity of the machine nor plan beyond their
+/// quota. The allocations are only known for the mock environments,
+/// see `cuda_env_mock_set_memory_usage`, and count as 0 otherwise.
+///
+/// The limit changes what is reported, it doesn't make allocations
+/// fail. It applies to the imports already built too.
+#[no_mangle]
+pub extern "C" fn cuda_env_set_memory_limit(cuda_env: &cuda_env_t, bytes: u64) {
+    *cuda_env.shared.memory_limit.lock().unwrap() = match bytes {
+        0 => None,
+        bytes => Some(bytes),
+    };
+}
+
+/// Whether the device of the current context has half-precision
+/// arithmetic, i.e. a compute capability of at least 5.3, so that an
+/// embedder can refuse the fp16 workloads up front rather than let
+/// them fail on the device.
+///
+/// Returns false, and sets the last error, if the device can't be
+/// queried, e.g. without a current context.
+#[no_mangle]
+pub extern "C" fn cuda_env_supports_half_precision(cuda_env: &cuda_env_t) -> bool {
+    let capability = current_device(&cuda_env.shared)
+        .and_then(|device| compute_capability(&cuda_env.shared, device));
+
+    match capability {
+        Ok(capability) => capability >= (5, 3),
+        Err(error) => {
+            log::debug!("cannot query the half-precision support: {}", error);
+            update_last_error(error);
+            false
+        }
+    }
+}
+
+/// add the device queries enabled on `cuda_env` to each namespace of
+/// `externs` that doesn't have them yet
+pub(super) fn add_device_functions(
+    store: &Store,
+    cuda_env: &cuda_env_t,
+    externs: &mut Vec<(String, String, Extern)>,
+) {
+    let env = DeviceEnv {
+        shared: cuda_env.shared.clone(),
+        memory: LazyInit::new(),
+    };
+
+    if cuda_env.is_import_enabled(DEVICE_TOTAL_MEM_NAME) {
+        add_to_each_namespace(externs, DEVICE_TOTAL_MEM_NAME, || {
+            Function::new_native_with_env(store, env.clone(), cu_device_total_mem)
+        });
+    }
+    if cuda_env.is_import_enabled(COMPUTE_CAPABILITY_NAME) {
+        add_to_each_namespace(externs, COMPUTE_CAPABILITY_NAME, || {
+            Function::new_native_with_env(store, env.clone(), cu_device_compute_capability)
+        });
+    }
+    if cuda_env.is_import_enabled(MEM_GET_INFO_NAME) {
+        add_to_each_namespace(externs, MEM_GET_INFO_NAME, || {
+            Function::new_native_with_env(store, env.clone(), cu_mem_get_info)
+        });
+    }
+    if cuda_env.is_import_enabled(DEVICE_PROPERTIES_NAME) {
+        add_to_each_namespace(externs, DEVICE_PROPERTIES_NAME, || {
+            Function::new_native_with_env(store, env.clone(), cuda_get_device_properties)
+        });
+    }
+}
+
+/// add `cudaGetDeviceProperties_v2`, if enabled on `cuda_env`, to the
+/// `"env"` namespace of `externs`, unless it is already there
+pub(super) fn add_runtime_functions(
+    store: &Store,
+    cuda_env: &cuda_env_t,
+    externs: &mut Vec<(String, String, Extern)>,
+) {
+    let registered = externs.iter().any(|(namespace, name, _)| {
+        namespace == RUNTIME_NAMESPACE && name == DEVICE_PROPERTIES_NAME
+    });
+    if registered || !cuda_env.is_import_enabled(DEVICE_PROPERTIES_NAME) {
+        return;
+    }
+
+    let env = DeviceEnv {
+        shared: cuda_env.shared.clone(),
+        memory: LazyInit::new(),
+    };
+    let mut runtime_externs = vec![(
+        RUNTIME_NAMESPACE.to_string(),
+        DEVICE_PROPERTIES_NAME.to_string(),
+        Extern::from(Function::new_native_with_env(
+            store,
+            env,
+            cuda_get_device_properties,
+        )),
+    )];
+    capabilities::mask_externs(store, &cuda_env.shared, &mut runtime_externs);
+
+    externs.extend(runtime_externs);
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cu_device_total_mem() {
+        use super::super::mock::{cuda_env_mock_set_total_mem, cuda_env_new_mock};
+        use super::super::{cuda_env_delete, cuda_env_last_error, cuda_namespaces};
+        use crate::wasm_c_api::types::wasm_byte_vec_t;
+        use wasmer_api::{imports, Instance, Module, NativeFunc, Store};
+
+        let store = Store::default();
+        let namespace = cuda_namespaces(&store).into_iter().next().unwrap();
+        let handle = cuda_env_new_mock();
+        let cuda_env = unsafe { &*handle };
+
+        let mut import_object = imports! {};
+        cuda_env.add_to_import(&store, &mut import_object);
+        let module = Module::new(
+            &store,
+            format!(
+                r#"(module
+                  (import "{}" "cuDeviceTotalMem" (func $total_mem (param i32 i32) (result i32)))
+                  (memory (export "memory") 1)
+                  (func (export "total_mem") (param i32 i32) (result i32)
+                    (call $total_mem (local.get 0) (local.get 1)))
+                  (func (export "load") (param i32) (result i64)
+                    (i64.load (local.get 0))))"#,
+                namespace,
+            ),
+        )
+        .unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let total_mem: NativeFunc<(i32, i32), i32> =
+            instance.exports.get_native_function("total_mem").unwrap();
+        let load: NativeFunc<i32, i64> = instance.exports.get_native_function("load").unwrap();
+
+        assert_eq!(total_mem.call(8, 0).unwrap(), 0);
+        assert_eq!(load.call(8).unwrap(), 16 << 30);
+
+        assert!(cuda_env_mock_set_total_mem(cuda_env, 6 << 30));
+        assert_eq!(total_mem.call(8, 0).unwrap(), 0);
+        assert_eq!(load.call(8).unwrap(), 6 << 30);
+
+        // an invalid ordinal is an error, not a trap
+        assert_eq!(total_mem.call(8, 1).unwrap(), 101);
+        assert_eq!(total_mem.call(8, -1).unwrap(), 101);
+        let mut message: wasm_byte_vec_t = Vec::new().into();
+        assert!(cuda_env_last_error(Some(cuda_env), &mut message));
+        assert_eq!(
+            message.as_slice(),
+            b"cuDeviceTotalMem failed with CUDA error 101"
+        );
+        drop(message);
+
+        // so is an out of bounds pointer
+        assert_eq!(total_mem.call(0x10000 - 4, 0).unwrap(), 1);
+        assert_eq!(load.call(8).unwrap(), 6 << 30);
+
+        unsafe { cuda_env_delete(handle) };
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cu_device_compute_capability() {
+        use super::super::capabilities::{cuda_capability_t, cuda_env_set_capabilities};
+        use super::super::mock::{cuda_env_mock_set_compute_capability, cuda_env_new_mock};
+        use super::super::{cuda_env_delete, cuda_namespaces};
+        use wasmer_api::{imports, Instance, Module, NativeFunc, Store};
+
+        let store = Store::default();
+        let namespace = cuda_namespaces(&store).into_iter().next().unwrap();
+        let handle = cuda_env_new_mock();
+        let cuda_env = unsafe { &*handle };
+        let module = Module::new(
+            &store,
+            format!(
+                r#"(module
+                  (import "{}" "cuDeviceComputeCapability" (func $capability (param i32 i32 i32) (result i32)))
+                  (memory (export "memory") 1)
+                  (func (export "capability") (param i32 i32 i32) (result i32)
+                    (call $capability (local.get 0) (local.get 1) (local.get 2)))
+                  (func (export "load") (param i32) (result i32)
+                    (i32.load (local.get 0))))"#,
+                namespace,
+            ),
+        )
+        .unwrap();
+        let instantiate = || {
+            let mut import_object = imports! {};
+            cuda_env.add_to_import(&store, &mut import_object);
+            let instance = Instance::new(&module, &import_object).unwrap();
+            let capability: NativeFunc<(i32, i32, i32), i32> =
+                instance.exports.get_native_function("capability").unwrap();
+            let load: NativeFunc<i32, i32> = instance.exports.get_native_function("load").unwrap();
+
+            (capability, load)
+        };
+
+        let (capability, load) = instantiate();
+        assert_eq!(capability.call(8, 12, 0).unwrap(), 0);
+        assert_eq!((load.call(8).unwrap(), load.call(12).unwrap()), (8, 0));
+
+        // tests can simulate other architectures
+        assert!(cuda_env_mock_set_compute_capability(cuda_env, 7, 5));
+        assert_eq!(capability.call(8, 12, 0).unwrap(), 0);
+        assert_eq!((load.call(8).unwrap(), load.call(12).unwrap()), (7, 5));
+
+        assert_eq!(capability.call(8, 12, 1).unwrap(), 101);
+        // nothing is written if either pointer is out of bounds
+        assert!(cuda_env_mock_set_compute_capability(cuda_env, 9, 0));
+        assert_eq!(capability.call(8, 0x10000, 0).unwrap(), 1);
+        assert_eq!(capability.call(0x10000, 12, 0).unwrap(), 1);
+        assert_eq!((load.call(8).unwrap(), load.call(12).unwrap()), (7, 5));
+
+        // it is a device query
+        cuda_env_set_capabilities(cuda_env, cuda_capability_t::CUDA_CAPABILITY_MEMORY as u32);
+        let (capability, _) = instantiate();
+        assert_eq!(capability.call(8, 12, 0).unwrap(), 800);
+
+        unsafe { cuda_env_delete(handle) };
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cu_mem_get_info() {
+        use super::super::mock::{
+            cuda_env_mock_set_free_mem, cuda_env_mock_set_total_mem, cuda_env_new_mock,
+        };
+        use super::super::{cuda_env_delete, cuda_namespaces};
+        use wasmer_api::{imports, Instance, Module, NativeFunc, Store};
+
+        let store = Store::default();
+        let namespace = cuda_namespaces(&store).into_iter().next().unwrap();
+        let handle = cuda_env_new_mock();
+        let cuda_env = unsafe { &*handle };
+
+        let mut import_object = imports! {};
+        cuda_env.add_to_import(&store, &mut import_object);
+        let module = Module::new(
+            &store,
+            format!(
+                r#"(module
+                  (import "{}" "cuMemGetInfo" (func $mem_info (param i32 i32) (result i32)))
+                  (memory (export "memory") 1)
+                  (func (export "mem_info") (param i32 i32) (result i32)
+                    (call $mem_info (local.get 0) (local.get 1)))
+                  (func (export "load") (param i32) (result i64)
+                    (i64.load (local.get 0))))"#,
+                namespace,
+            ),
+        )
+        .unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let mem_info: NativeFunc<(i32, i32), i32> =
+            instance.exports.get_native_function("mem_info").unwrap();
+        let load: NativeFunc<i32, i64> = instance.exports.get_native_function("load").unwrap();
+        let read = || {
+            assert_eq!(mem_info.call(8, 16).unwrap(), 0);
+            (load.call(8).unwrap(), load.call(16).unwrap())
+        };
+
+        assert_eq!(read(), (16 << 30, 16 << 30));
+
+        assert!(cuda_env_mock_set_free_mem(cuda_env, 1 << 30));
+        assert_eq!(read(), (1 << 30, 16 << 30));
+
+        // the free memory never exceeds the total
+        assert!(cuda_env_mock_set_total_mem(cuda_env, (1 << 30) - 1));
+        assert_eq!(read(), ((1 << 30) - 1, (1 << 30) - 1));
+
+        // nothing is written if either pointer is out of bounds
+        assert!(cuda_env_mock_set_free_mem(cuda_env, 1 << 20));
+        assert_eq!(mem_info.call(8, 0x10000 - 7).unwrap(), 1);
+        assert_eq!(load.call(8).unwrap(), (1 << 30) - 1);
+
+        unsafe { cuda_env_delete(handle) };
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_env_set_memory_limit() {
+        use super::super::mock::{
+            cuda_env_mock_set_free_mem, cuda_env_mock_set_memory_usage, cuda_env_new_mock,
+        };
+        use super::super::{cuda_env_delete, cuda_namespaces};
+        use super::cuda_env_set_memory_limit;
+        use wasmer_api::{imports, Instance, Module, NativeFunc, Store};
+
+        let store = Store::default();
+        let namespace = cuda_namespaces(&store).into_iter().next().unwrap();
+        let handle = cuda_env_new_mock();
+        let cuda_env = unsafe { &*handle };
+
+        let mut import_object = imports! {};
+        cuda_env.add_to_import(&store, &mut import_object);
+        let module = Module::new(
+            &store,
+            format!(
+                r#"(module
+                  (import "{0}" "cuMemGetInfo" (func $mem_info (param i32 i32) (result i32)))
+                  (import "{0}" "cuDeviceTotalMem" (func $total_mem (param i32 i32) (result i32)))
+                  (memory (export "memory") 1)
+                  (func (export "mem_info") (param i32 i32) (result i32)
+                    (call $mem_info (local.get 0) (local.get 1)))
+                  (func (export "total_mem") (param i32 i32) (result i32)
+                    (call $total_mem (local.get 0) (local.get 1)))
+                  (func (export "load") (param i32) (result i64)
+                    (i64.load (local.get 0))))"#,
+                namespace,
+            ),
+        )
+        .unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let mem_info: NativeFunc<(i32, i32), i32> =
+            instance.exports.get_native_function("mem_info").unwrap();
+        let total_mem: NativeFunc<(i32, i32), i32> =
+            instance.exports.get_native_function("total_mem").unwrap();
+        let load: NativeFunc<i32, i64> = instance.exports.get_native_function("load").unwrap();
+        let read = || {
+            assert_eq!(mem_info.call(8, 16).unwrap(), 0);
+            assert_eq!(total_mem.call(24, 0).unwrap(), 0);
+            assert_eq!(load.call(16).unwrap(), load.call(24).unwrap());
+            (load.call(8).unwrap(), load.call(16).unwrap())
+        };
+
+        // without a limit, the values of the device pass through
+        assert!(cuda_env_mock_set_free_mem(cuda_env, 12 << 30));
+        assert!(cuda_env_mock_set_memory_usage(cuda_env, 1 << 30));
+        assert_eq!(read(), (12 << 30, 16 << 30));
+
+        // the total is the limit, and the free memory what is left of it
+        cuda_env_set_memory_limit(cuda_env, 4 << 30);
+        assert_eq!(read(), (3 << 30, 4 << 30));
+
+        // unless the device has less
+        assert!(cuda_env_mock_set_free_mem(cuda_env, 1 << 30));
+        assert_eq!(read(), (1 << 30, 4 << 30));
+        assert!(cuda_env_mock_set_free_mem(cuda_env, 12 << 30));
+
+        // near the limit
+        assert!(cuda_env_mock_set_memory_usage(cuda_env, (4 << 30) - 1));
+        assert_eq!(read(), (1, 4 << 30));
+        assert!(cuda_env_mock_set_memory_usage(cuda_env, 4 << 30));
+        assert_eq!(read(), (0, 4 << 30));
+        assert!(cuda_env_mock_set_memory_usage(cuda_env, (4 << 30) + 1));
+        assert_eq!(read(), (0, 4 << 30));
+
+        // a limit above the device is still the reported total
+        cuda_env_set_memory_limit(cuda_env, 32 << 30);
+        assert!(cuda_env_mock_set_memory_usage(cuda_env, 0));
+        assert_eq!(read(), (12 << 30, 32 << 30));
+
+        cuda_env_set_memory_limit(cuda_env, 0);
+        assert_eq!(read(), (12 << 30, 16 << 30));
+
+        unsafe { cuda_env_delete(handle) };
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_get_device_properties() {
+        use super::super::mock::{cuda_env_mock_set_total_mem, cuda_env_new_mock};
+        use super::super::{cuda_env_delete, cuda_namespaces};
+        use wasmer_api::{imports, Instance, Module, NativeFunc, Store};
+
+        let store = Store::default();
+        let namespace = cuda_namespaces(&store).into_iter().next().unwrap();
+        let handle = cuda_env_new_mock();
+        let cuda_env = unsafe { &*handle };
+
+        let mut import_object = imports! {};
+        cuda_env.add_to_import(&store, &mut import_object);
+        let module = Module::new(
+            &store,
+            format!(
+                r#"(module
+                  (import "{}" "cudaGetDeviceProperties_v2" (func $properties (param i32 i32) (result i32)))
+                  (import "env" "cudaGetDeviceProperties_v2" (func $env_properties (param i32 i32) (result i32)))
+                  (memory (export "memory") 1)
+                  (func (export "properties") (param i32 i32) (result i32)
+                    (call $properties (local.get 0) (local.get 1)))
+                  (func (export "env_properties") (param i32 i32) (result i32)
+                    (call $env_properties (local.get 0) (local.get 1))))"#,
+                namespace,
+            ),
+        )
+        .unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let properties: NativeFunc<(i32, i32), i32> =
+            instance.exports.get_native_function("properties").unwrap();
+        let env_properties: NativeFunc<(i32, i32), i32> = instance
+            .exports
+            .get_native_function("env_properties")
+            .unwrap();
+        let memory = instance.exports.get_memory("memory").unwrap();
+        let read = |offset: usize, len: usize| {
+            memory.view::<u8>()[offset..offset + len]
+                .iter()
+                .map(|cell| cell.get())
+                .collect::<Vec<_>>()
+        };
+        let int = |offset: usize| {
+            let bytes = read(1024 + offset, 4);
+            i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        };
+        memory.view::<u8>()[1024..2048]
+            .iter()
+            .for_each(|cell| cell.set(0xaa));
+
+        assert_eq!(properties.call(1024, 0).unwrap(), 0);
+        let name = read(1024, 256);
+        assert_eq!(&name[..16], b"Wasmer Mock GPU\0");
+        assert!(name[16..].iter().all(|byte| *byte == 0));
+        // totalGlobalMem saturates, as a 32-bit size_t
+        assert_eq!(int(284) as u32, u32::MAX);
+        assert_eq!(int(296), 32);
+        assert_eq!((int(308), int(312), int(316)), (1024, 1024, 64));
+        assert_eq!((int(340), int(344)), (8, 0));
+        assert_eq!(int(360), 108);
+        // the rest of the struct is untouched
+        assert_eq!(read(1024 + 364, 4), vec![0xaa; 4]);
+
+        assert!(cuda_env_mock_set_total_mem(cuda_env, 1 << 30));
+        assert_eq!(properties.call(1024, 0).unwrap(), 0);
+        assert_eq!(int(284), 1 << 30);
+
+        assert_eq!(properties.call(1024, 1).unwrap(), 101);
+        assert_eq!(properties.call(0x10000 - 100, 0).unwrap(), 1);
+
+        // the same struct is written by the import of the "env" namespace
+        assert_eq!(env_properties.call(2048, 0).unwrap(), 0);
+        assert_eq!(read(2048, 364), read(1024, 364));
+
+        unsafe { cuda_env_delete(handle) };
+    }
+
+    #[test]
+    fn test_cuda_env_supports_half_precision() {
+        use super::super::cuda_env_delete;
+        use super::super::mock::{cuda_env_mock_set_compute_capability, cuda_env_new_mock};
+        use super::cuda_env_supports_half_precision;
+
+        let handle = cuda_env_new_mock();
+        let cuda_env = unsafe { &*handle };
+
+        // 8.0 by default
+        assert!(cuda_env_supports_half_precision(cuda_env));
+
+        let capabilities = [(5, 2, false), (5, 3, true), (6, 0, true), (3, 7, false)];
+        for &(major, minor, supported) in &capabilities {
+            assert!(cuda_env_mock_set_compute_capability(cuda_env, major, minor));
+            assert_eq!(cuda_env_supports_half_precision(cuda_env), supported);
+        }
+
+        unsafe { cuda_env_delete(handle) };
+    }
+}
@@ -0,0 +1,271 @@
+//! The CUDA driver, loaded at runtime with `libloading`, for the few
+//! calls the C API makes itself, e.g. `cuGetErrorName` or the device
+//! queries.
+//!
+//! It is the same `libcuda` as the one `wasmer-cuda` uses, so it
+//! shares its devices and the current context of each thread.
+
+use super::CudaError;
+use lazy_static::lazy_static;
+use libloading::Library;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+
+/// `CUDA_SUCCESS`
+pub(super) const CUDA_SUCCESS: i32 = 0;
+
+/// `CUDA_ERROR_INVALID_VALUE`, e.g. for an out of bounds pointer
+pub(super) const CUDA_ERROR_INVALID_VALUE: i32 = 1;
+
+/// `CUDA_ERROR_NO_DEVICE`, returned when the driver cannot be loaded
+pub(super) const CUDA_ERROR_NO_DEVICE: i32 = 100;
+
+/// `CUDA_ERROR_INVALID_DEVICE`, for an invalid device ordinal
+pub(super) const CUDA_ERROR_INVALID_DEVICE: i32 = 101;
+
+/// `CUDA_ERROR_NOT_SUPPORTED`, e.g. for a cooperative launch on a
+/// device without it
+pub(super) const CUDA_ERROR_NOT_SUPPORTED: i32 = 801;
+
+/// `CUDA_ERROR_UNKNOWN`
+pub(super) const CUDA_ERROR_UNKNOWN: i32 = 999;
+
+#[cfg(windows)]
+const DRIVER_LIBRARIES: &[&str] = &["nvcuda.dll"];
+#[cfg(not(windows))]
+const DRIVER_LIBRARIES: &[&str] = &["libcuda.so.1", "libcuda.so"];
+
+pub(super) type CuGetErrorText = unsafe extern "C" fn(error: i32, text: *mut *const c_char) -> i32;
+type CuInit = unsafe extern "C" fn(flags: c_uint) -> i32;
+type CuDeviceGetCount = unsafe extern "C" fn(count: *mut c_int) -> i32;
+type CuDeviceTotalMem = unsafe extern "C" fn(bytes: *mut usize, device: c_int) -> i32;
+type CuMemGetInfo = unsafe extern "C" fn(free: *mut usize, total: *mut usize) -> i32;
+type CuDeviceGetName = unsafe extern "C" fn(name: *mut c_char, len: c_int, device: c_int) -> i32;
+type CuDeviceGetAttribute =
+    unsafe extern "C" fn(value: *mut c_int, attribute: c_int, device: c_int) -> i32;
+type CuCtxGetDevice = unsafe extern "C" fn(device: *mut c_int) -> i32;
+type CuOccupancyMaxActiveBlocksPerMultiprocessor = unsafe extern "C" fn(
+    num_blocks: *mut c_int,
+    func: *mut c_void,
+    block_size: c_int,
+    dynamic_shared_mem: usize,
+) -> i32;
+
+/// `CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK: i32 = 1;
+/// `CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_X`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_X: i32 = 2;
+/// `CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Y`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Y: i32 = 3;
+/// `CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Z`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Z: i32 = 4;
+/// `CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_X`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_X: i32 = 5;
+/// `CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Y`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Y: i32 = 6;
+/// `CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Z`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Z: i32 = 7;
+/// `CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK: i32 = 8;
+/// `CU_DEVICE_ATTRIBUTE_TOTAL_CONSTANT_MEMORY`
+pub(super) const CU_DEVICE_ATTRIBUTE_TOTAL_CONSTANT_MEMORY: i32 = 9;
+/// `CU_DEVICE_ATTRIBUTE_WARP_SIZE`
+pub(super) const CU_DEVICE_ATTRIBUTE_WARP_SIZE: i32 = 10;
+/// `CU_DEVICE_ATTRIBUTE_MAX_PITCH`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_PITCH: i32 = 11;
+/// `CU_DEVICE_ATTRIBUTE_MAX_REGISTERS_PER_BLOCK`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_REGISTERS_PER_BLOCK: i32 = 12;
+/// `CU_DEVICE_ATTRIBUTE_CLOCK_RATE`
+pub(super) const CU_DEVICE_ATTRIBUTE_CLOCK_RATE: i32 = 13;
+/// `CU_DEVICE_ATTRIBUTE_TEXTURE_ALIGNMENT`
+pub(super) const CU_DEVICE_ATTRIBUTE_TEXTURE_ALIGNMENT: i32 = 14;
+/// `CU_DEVICE_ATTRIBUTE_GPU_OVERLAP`
+pub(super) const CU_DEVICE_ATTRIBUTE_GPU_OVERLAP: i32 = 15;
+/// `CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT`
+pub(super) const CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT: i32 = 16;
+/// `CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_MULTIPROCESSOR`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_MULTIPROCESSOR: i32 = 39;
+/// `CU_DEVICE_ATTRIBUTE_TEXTURE_PITCH_ALIGNMENT`
+pub(super) const CU_DEVICE_ATTRIBUTE_TEXTURE_PITCH_ALIGNMENT: i32 = 51;
+/// `CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR`
+pub(super) const CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR: i32 = 75;
+/// `CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR`
+pub(super) const CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR: i32 = 76;
+/// `CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_MULTIPROCESSOR`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_MULTIPROCESSOR: i32 = 81;
+/// `CU_DEVICE_ATTRIBUTE_COOPERATIVE_LAUNCH`
+pub(super) const CU_DEVICE_ATTRIBUTE_COOPERATIVE_LAUNCH: i32 = 95;
+/// `CU_DEVICE_ATTRIBUTE_MAX_BLOCKS_PER_MULTIPROCESSOR`
+pub(super) const CU_DEVICE_ATTRIBUTE_MAX_BLOCKS_PER_MULTIPROCESSOR: i32 = 106;
+
+pub(super) struct Driver {
+    pub(super) get_error_name: CuGetErrorText,
+    pub(super) get_error_string: CuGetErrorText,
+    init: CuInit,
+    device_get_count: CuDeviceGetCount,
+    device_total_mem: CuDeviceTotalMem,
+    device_get_attribute: CuDeviceGetAttribute,
+    mem_get_info: CuMemGetInfo,
+    device_get_name: CuDeviceGetName,
+    ctx_get_device: CuCtxGetDevice,
+    occupancy_max_active_blocks: CuOccupancyMaxActiveBlocksPerMultiprocessor,
+    // keep the library loaded as long as the function pointers live
+    _library: Library,
+}
+
+impl Driver {
+    unsafe fn load() -> Option<Self> {
+        let library = DRIVER_LIBRARIES
+            .iter()
+            .find_map(|name| Library::new(name).ok())?;
+        let get_error_name = *library.get::<CuGetErrorText>(b"cuGetErrorName\0").ok()?;
+        let get_error_string = *library.get::<CuGetErrorText>(b"cuGetErrorString\0").ok()?;
+        let init = *library.get::<CuInit>(b"cuInit\0").ok()?;
+        let device_get_count = *library
+            .get::<CuDeviceGetCount>(b"cuDeviceGetCount\0")
+            .ok()?;
+        let device_total_mem = *library
+            .get::<CuDeviceTotalMem>(b"cuDeviceTotalMem_v2\0")
+            .ok()?;
+        let device_get_attribute = *library
+            .get::<CuDeviceGetAttribute>(b"cuDeviceGetAttribute\0")
+            .ok()?;
+        let mem_get_info = *library.get::<CuMemGetInfo>(b"cuMemGetInfo_v2\0").ok()?;
+        let device_get_name = *library.get::<CuDeviceGetName>(b"cuDeviceGetName\0").ok()?;
+        let ctx_get_device = *library.get::<CuCtxGetDevice>(b"cuCtxGetDevice\0").ok()?;
+        let occupancy_max_active_blocks = *library
+            .get::<CuOccupancyMaxActiveBlocksPerMultiprocessor>(
+                b"cuOccupancyMaxActiveBlocksPerMultiprocessor\0",
+            )
+            .ok()?;
+
+        Some(Self {
+            get_error_name,
+            get_error_string,
+            init,
+            device_get_count,
+            device_total_mem,
+            device_get_attribute,
+            mem_get_info,
+            device_get_name,
+            ctx_get_device,
+            occupancy_max_active_blocks,
+            _library: library,
+        })
+    }
+
+    /// the number of devices
+    pub(super) fn device_count(&self) -> Result<i32, CudaError> {
+        let mut count = 0;
+
+        match unsafe { (self.device_get_count)(&mut count) } {
+            CUDA_SUCCESS => Ok(count),
+            error => Err(CudaError::Driver(error)),
+        }
+    }
+
+    /// the total memory of the device `device`, in bytes
+    pub(super) fn device_total_mem(&self, device: i32) -> Result<u64, CudaError> {
+        let mut bytes = 0;
+
+        match unsafe { (self.device_total_mem)(&mut bytes, device) } {
+            CUDA_SUCCESS => Ok(bytes as u64),
+            error => Err(CudaError::Driver(error)),
+        }
+    }
+
+    /// the name of the device `device`
+    pub(super) fn device_name(&self, device: i32) -> Result<String, CudaError> {
+        let mut name = [0 as c_char; 256];
+
+        match unsafe { (self.device_get_name)(name.as_mut_ptr(), name.len() as c_int, device) } {
+            CUDA_SUCCESS => {
+                let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+
+                Ok(name.to_string_lossy().into_owned())
+            }
+            error => Err(CudaError::Driver(error)),
+        }
+    }
+
+    /// the `(free, total)` memory of the device of the current context
+    /// of this thread, in bytes
+    pub(super) fn mem_info(&self) -> Result<(u64, u64), CudaError> {
+        let (mut free, mut total) = (0, 0);
+
+        match unsafe { (self.mem_get_info)(&mut free, &mut total) } {
+            CUDA_SUCCESS => Ok((free as u64, total as u64)),
+            error => Err(CudaError::Driver(error)),
+        }
+    }
+
+    /// the `CUdevice_attribute` `attribute` of the device `device`
+    pub(super) fn device_attribute(&self, attribute: i32, device: i32) -> Result<i32, CudaError> {
+        let mut value = 0;
+
+        match unsafe { (self.device_get_attribute)(&mut value, attribute, device) } {
+            CUDA_SUCCESS => Ok(value),
+            error => Err(CudaError::Driver(error)),
+        }
+    }
+
+    /// the device of the current context of this thread
+    pub(super) fn current_device(&self) -> Result<i32, CudaError> {
+        let mut device = 0;
+
+        match unsafe { (self.ctx_get_device)(&mut device) } {
+            CUDA_SUCCESS => Ok(device),
+            error => Err(CudaError::Driver(error)),
+        }
+    }
+
+    /// the number of blocks of `block_size` threads and
+    /// `dynamic_shared_mem` bytes of shared memory of the kernel `func`
+    /// that fit at once on a multiprocessor
+    ///
+    /// # Safety
+    ///
+    /// `func` must be a `CUfunction` of the current context.
+    pub(super) unsafe fn occupancy_max_active_blocks(
+        &self,
+        func: u64,
+        block_size: i32,
+        dynamic_shared_mem: usize,
+    ) -> Result<i32, CudaError> {
+        let mut num_blocks = 0;
+
+        match (self.occupancy_max_active_blocks)(
+            &mut num_blocks,
+            func as usize as *mut c_void,
+            block_size,
+            dynamic_shared_mem,
+        ) {
+            CUDA_SUCCESS => Ok(num_blocks),
+            error => Err(CudaError::Driver(error)),
+        }
+    }
+}
+
+lazy_static! {
+    static ref DRIVER: Option<Driver> = unsafe { Driver::load() };
+    /// the result of `cuInit`
+    static ref INIT: i32 = match DRIVER.as_ref() {
+        Some(driver) => unsafe { (driver.init)(0) },
+        None => CUDA_ERROR_NO_DEVICE,
+    };
+}
+
+/// the driver, if it can be loaded
+pub(super) fn driver() -> Option<&'static Driver> {
+    DRIVER.as_ref()
+}
+
+/// the driver, after `cuInit`, or the error of the failure
+pub(super) fn initialized_driver() -> Result<&'static Driver, CudaError> {
+    let driver = DRIVER.as_ref().ok_or(CudaError::NoDevice)?;
+
+    match *INIT {
+        CUDA_SUCCESS => Ok(driver),
+        error => Err(CudaError::Driver(error)),
+    }
+}
@@ -0,0 +1,168 @@
+//! `cuda_error_name` and `cuda_error_string`, so that a guest can turn
+//! a `CUresult` into the same name and description as native CUDA
+//! code, without its own copy of the table.
+//!
+//! They are not `cuGetErrorName` and `cuGetErrorString`: those hand
+//! out a pointer to a static string of the driver, which a guest can't
+//! read, so these copy the string into a guest buffer instead and take
+//! `(code, buf, len)`. The names leave the CUDA ones free for a
+//! wasmer-cuda version that provides them.
+//!
+//! The strings come from the CUDA driver, see the `driver` module. If
+//! it cannot be loaded, or doesn't know the code, a
+//! built-in table of the common codes is used, and any other code is
+//! an `"unknown error"`.
+//!
+//! Both functions are registered in every namespace holding cuda
+//! imports, unless the namespace already provides them.
+
+use super::add_to_each_namespace;
+use super::driver::{driver, CuGetErrorText};
+use super::supported::write_to_guest;
+use std::ffi::CStr;
+use std::ptr;
+use wasmer_api::{
+    Array, Extern, Function, HostEnvInitError, Instance, LazyInit, Memory, Store, WasmPtr,
+    WasmerEnv,
+};
+
+pub(super) const ERROR_NAME_NAME: &str = "cuda_error_name";
+pub(super) const ERROR_STRING_NAME: &str = "cuda_error_string";
+
+const UNKNOWN_ERROR: &str = "unknown error";
+
+/// `(code, name, description)` of the common `CUresult` codes
+const ERRORS: &[(i32, &str, &str)] = &[
+    (0, "CUDA_SUCCESS", "no error"),
+    (1, "CUDA_ERROR_INVALID_VALUE", "invalid argument"),
+    (2, "CUDA_ERROR_OUT_OF_MEMORY", "out of memory"),
+    (3, "CUDA_ERROR_NOT_INITIALIZED", "initialization error"),
+    (4, "CUDA_ERROR_DEINITIALIZED", "driver shutting down"),
+    (
+        100,
+        "CUDA_ERROR_NO_DEVICE",
+        "no CUDA-capable device is detected",
+    ),
+    (101, "CUDA_ERROR_INVALID_DEVICE", "invalid device ordinal"),
+    (
+        200,
+        "CUDA_ERROR_INVALID_IMAGE",
+        "device kernel image is invalid",
+    ),
+    (201, "CUDA_ERROR_INVALID_CONTEXT", "invalid device context"),
+    (
+        209,
+        "CUDA_ERROR_NO_BINARY_FOR_GPU",
+        "no kernel image is available for execution on the device",
+    ),
+    (400, "CUDA_ERROR_INVALID_HANDLE", "invalid resource handle"),
+    (500, "CUDA_ERROR_NOT_FOUND", "named symbol not found"),
+    (600, "CUDA_ERROR_NOT_READY", "device not ready"),
+    (
+        700,
+        "CUDA_ERROR_ILLEGAL_ADDRESS",
+        "an illegal memory access was encountered",
+    ),
+    (
+        701,
+        "CUDA_ERROR_LAUNCH_OUT_OF_RESOURCES",
+        "too many resources requested for launch",
+    ),
+    (
+        702,
+        "CUDA_ERROR_LAUNCH_TIMEOUT",
+        "the launch timed out and was terminated",
+    ),
+    (
+        719,
+        "CUDA_ERROR_LAUNCH_FAILED",
+        "unspecified launch failure",
+    ),
+    (800, "CUDA_ERROR_NOT_PERMITTED", "operation not permitted"),
+    (801, "CUDA_ERROR_NOT_SUPPORTED", "operation not supported"),
+    (999, "CUDA_ERROR_UNKNOWN", "unknown error"),
+];
+
+/// the text the driver gives for `code` with `get_error_text`, if any
+fn driver_text(get_error_text: CuGetErrorText, code: i32) -> Option<String> {
+    let mut text = ptr::null();
+
+    unsafe {
+        if get_error_text(code, &mut text) != 0 || text.is_null() {
+            return None;
+        }
+
+        Some(CStr::from_ptr(text).to_string_lossy().into_owned())
+    }
+}
+
+/// the name of the `CUresult` `code`, e.g. `CUDA_ERROR_OUT_OF_MEMORY`
+fn error_name(code: i32) -> String {
+    driver()
+        .and_then(|driver| driver_text(driver.get_error_name, code))
+        .or_else(|| {
+            ERRORS
+                .iter()
+                .find(|(known, _, _)| *known == code)
+                .map(|(_, name, _)| name.to_string())
+        })
+        .unwrap_or_else(|| UNKNOWN_ERROR.to_string())
+}
+
+/// the description of the `CUresult` `code`, e.g. `out of memory`
+fn error_string(code: i32) -> String {
+    driver()
+        .and_then(|driver| driver_text(driver.get_error_string, code))
+        .or_else(|| {
+            ERRORS
+                .iter()
+                .find(|(known, _, _)| *known == code)
+                .map(|(_, _, description)| description.to_string())
+        })
+        .unwrap_or_else(|| UNKNOWN_ERROR.to_string())
+}
+
+#[derive(Clone, Default)]
+struct ErrorStringEnv {
+    memory: LazyInit<Memory>,
+}
+
+impl WasmerEnv for ErrorStringEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        let memory: Memory = instance.exports.get_with_generics_weak("memory")?;
+        self.memory.initialize(memory);
+        Ok(())
+    }
+}
+
+/// Copy as much of the name of `code` as fits in the `len` bytes at
+/// `buf`, and return the length of the whole name.
+///
+/// The name isn't nul-terminated. Returns -1 if `buf` is out of
+/// bounds.
+fn cu_get_error_name(env: &ErrorStringEnv, code: i32, buf: WasmPtr<u8, Array>, len: i32) -> i32 {
+    let name = error_name(code).into_bytes();
+
+    write_to_guest(&env.memory, ERROR_NAME_NAME, buf, len, &name)
+}
+
+/// Like `cu_get_error_name`, for the description of `code`.
+fn cu_get_error_string(env: &ErrorStringEnv, code: i32, buf: WasmPtr<u8, Array>, len: i32) -> i32 {
+    let description = error_string(code).into_bytes();
+
+    write_to_guest(&env.memory, ERROR_STRING_NAME, buf, len, &description)
+}
+
+/// add `cuda_error_name` and `cuda_error_string` to each namespace of
+/// `externs` that doesn't have them yet
+pub(super) fn add_error_string_functions(
+    store: &Store,
+    externs: &mut Vec<(String, String, Extern)>,
+) {
+    add_to_each_namespace(externs, ERROR_NAME_NAME, || {
+        Function::new_native_with_env(store, ErrorStringEnv::default(), cu_get_error_name)
+    });
+    add_to_each_namespace(externs, ERROR_STRING_NAME, || {
+        Function::new_native_with_env(store, ErrorStringEnv::default(), cu_get_error_string)
+    });
+}
@@ -0,0 +1,60 @@
+//! `cuGetLastErrorString`, so that a guest can read the last error
+//! recorded on its `cuda_env_t`, the same message the host gets with
+//! `cuda_env_last_error`.
+//!
+//! It is registered in every namespace holding cuda imports, unless
+//! the namespace already provides it.
+
+use super::supported::write_to_guest;
+use super::{add_to_each_namespace, CudaEnvShared};
+use std::sync::Arc;
+use wasmer_api::{
+    Array, Extern, Function, HostEnvInitError, Instance, LazyInit, Memory, Store, WasmPtr,
+    WasmerEnv,
+};
+
+pub(super) const LAST_ERROR_STRING_NAME: &str = "cuGetLastErrorString";
+
+#[derive(Clone)]
+struct LastErrorEnv {
+    shared: Arc<CudaEnvShared>,
+    memory: LazyInit<Memory>,
+}
+
+impl WasmerEnv for LastErrorEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        let memory: Memory = instance.exports.get_with_generics_weak("memory")?;
+        self.memory.initialize(memory);
+        Ok(())
+    }
+}
+
+/// Copy as much of the last error message as fits in the `len` bytes
+/// at `buf`, and return the length of the whole message, or 0 if no
+/// error has been recorded.
+///
+/// The message isn't nul-terminated. Returns -1 if `buf` is out of
+/// bounds.
+fn cu_get_last_error_string(env: &LastErrorEnv, buf: WasmPtr<u8, Array>, len: i32) -> i32 {
+    let last_error = env.shared.last_error.lock().unwrap().clone();
+    let message = last_error.unwrap_or_default().into_bytes();
+
+    write_to_guest(&env.memory, LAST_ERROR_STRING_NAME, buf, len, &message)
+}
+
+/// add `cuGetLastErrorString` to each namespace of `externs` that
+/// doesn't have it yet
+pub(super) fn add_last_error_functions(
+    store: &Store,
+    shared: &Arc<CudaEnvShared>,
+    externs: &mut Vec<(String, String, Extern)>,
+) {
+    add_to_each_namespace(externs, LAST_ERROR_STRING_NAME, || {
+        let env = LastErrorEnv {
+            shared: shared.clone(),
+            memory: LazyInit::new(),
+        };
+
+        Function::new_native_with_env(store, env, cu_get_last_error_string)
+    });
+}
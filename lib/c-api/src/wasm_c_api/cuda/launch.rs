@@ -0,0 +1,161 @@
+//! `cuda_env_validate_cooperative_launch`, to check a cooperative
+//! launch before submitting it.
+//!
+//! `cuLaunchCooperativeKernel` needs every block of the grid to be
+//! resident on the device at once, so that they can synchronize with
+//! each other. A larger grid can't make progress, and fails or produces
+//! wrong results depending on the driver, so an embedder, or the launch
+//! import of `wasmer-cuda`, should reject it beforehand.
+//!
+//! The device is the one of the current context of the calling thread,
+//! answered by the CUDA driver, see the `driver` module, or the mock
+//! device of the environments made by `cuda_env_new_mock`.
+
+use super::device::{current_device, device_attribute};
+use super::driver::{
+    initialized_driver, CUDA_ERROR_INVALID_VALUE, CUDA_ERROR_NOT_SUPPORTED,
+    CU_DEVICE_ATTRIBUTE_COOPERATIVE_LAUNCH, CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK,
+    CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK, CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT,
+};
+use super::{cuda_env_t, CudaEnvShared, CudaError};
+use crate::error::update_last_error;
+
+/// the largest grid, in blocks, of a cooperative launch of `func`
+fn max_cooperative_grid(
+    shared: &CudaEnvShared,
+    func: u64,
+    block_size: u32,
+    shared_mem: usize,
+) -> Result<u32, CudaError> {
+    let device = current_device(shared)?;
+    let attribute = |attribute| device_attribute(shared, attribute, device);
+
+    if attribute(CU_DEVICE_ATTRIBUTE_COOPERATIVE_LAUNCH)? == 0 {
+        return Err(CudaError::Driver(CUDA_ERROR_NOT_SUPPORTED));
+    }
+    let max_threads = attribute(CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK)?;
+    let max_shared_mem = attribute(CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK)?;
+    if block_size == 0 || block_size > max_threads as u32 || shared_mem > max_shared_mem as usize {
+        return Err(CudaError::Driver(CUDA_ERROR_INVALID_VALUE));
+    }
+
+    let blocks_per_multiprocessor = match &shared.mock {
+        Some(mock) => mock.device().occupancy(block_size, shared_mem),
+        None => unsafe {
+            initialized_driver()?.occupancy_max_active_blocks(
+                func,
+                block_size as i32,
+                shared_mem,
+            )?
+        },
+    };
+    let multiprocessors = attribute(CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT)?;
+
+    Ok((blocks_per_multiprocessor.max(0) as u32).saturating_mul(multiprocessors.max(0) as u32))
+}
+
+/// Write at `out_max_grid` the largest grid, in blocks, of a
+/// cooperative launch of the kernel `func_handle`, with blocks of
+/// `block_size` threads and `shared_mem` bytes of dynamic shared
+/// memory, on the device of the current context: the number of such
+/// blocks resident at once on a multiprocessor, times the number of
+/// multiprocessors.
+///
+/// `func_handle` is the `CUfunction` of the kernel, in the current
+/// context. It is ignored by the mock environments, whose kernels are
+/// only limited by the threads, blocks and shared memory of a
+/// multiprocessor.
+///
+/// Returns false, and sets the last error, if `out_max_grid` is
+/// `NULL`, if the device doesn't support cooperative launches, if the
+/// block is larger than the device allows, or if the driver fails.
+///
+/// # Safety
+///
+/// `func_handle` must be a valid `CUfunction`, unless `cuda_env` is a
+/// mock environment.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_env_validate_cooperative_launch(
+    cuda_env: &cuda_env_t,
+    func_handle: u64,
+    block_size: u32,
+    shared_mem: usize,
+    out_max_grid: *mut u32,
+) -> bool {
+    if out_max_grid.is_null() {
+        update_last_error(CudaError::NullArgument("out_max_grid"));
+        return false;
+    }
+
+    match max_cooperative_grid(&cuda_env.shared, func_handle, block_size, shared_mem) {
+        Ok(max_grid) => {
+            *out_max_grid = max_grid;
+            true
+        }
+        Err(error) => {
+            log::debug!("cannot validate the cooperative launch: {}", error);
+            update_last_error(error);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_cuda_env_validate_cooperative_launch() {
+        use super::super::cuda_env_delete;
+        use super::super::mock::{cuda_env_mock_set_compute_capability, cuda_env_new_mock};
+        use super::cuda_env_validate_cooperative_launch;
+        use crate::error::take_last_error;
+        use std::ptr;
+
+        let handle = cuda_env_new_mock();
+        let cuda_env = unsafe { &*handle };
+        let max_grid = |block_size, shared_mem| {
+            let mut max_grid = 0;
+            match unsafe {
+                cuda_env_validate_cooperative_launch(
+                    cuda_env,
+                    0,
+                    block_size,
+                    shared_mem,
+                    &mut max_grid,
+                )
+            } {
+                true => Ok(max_grid),
+                false => Err(take_last_error().unwrap()),
+            }
+        };
+
+        // 108 multiprocessors of 2048 threads
+        assert_eq!(max_grid(256, 0), Ok(8 * 108));
+        assert_eq!(max_grid(1024, 0), Ok(2 * 108));
+        // at most 32 blocks per multiprocessor
+        assert_eq!(max_grid(32, 0), Ok(32 * 108));
+        // and 164 KiB of shared memory
+        assert_eq!(max_grid(256, 48 << 10), Ok(3 * 108));
+
+        // the block must fit on the device
+        assert_eq!(max_grid(0, 0), Err("CUDA driver error 1".to_string()));
+        assert_eq!(max_grid(2048, 0), Err("CUDA driver error 1".to_string()));
+        assert_eq!(
+            max_grid(256, (48 << 10) + 1),
+            Err("CUDA driver error 1".to_string())
+        );
+
+        assert!(!unsafe {
+            cuda_env_validate_cooperative_launch(cuda_env, 0, 256, 0, ptr::null_mut())
+        });
+        assert_eq!(
+            take_last_error().unwrap(),
+            "the `out_max_grid` argument is NULL"
+        );
+
+        // cooperative launches need a 6.0 device
+        assert!(cuda_env_mock_set_compute_capability(cuda_env, 5, 2));
+        assert_eq!(max_grid(256, 0), Err("CUDA driver error 801".to_string()));
+
+        unsafe { cuda_env_delete(handle) };
+    }
+}
@@ -0,0 +1,498 @@
+//! Mock CUDA environments, to test the GPU code paths of an embedder,
+//! e.g. on CI systems and laptops without NVIDIA GPUs.
+//!
+//! The cuda imports of a mock `cuda_env_t` have the names and types of
+//! the real ones, so that modules resolve and instantiate the same
+//! way, but they never call CUDA. Each call is recorded, and returns
+//! the result configured with `cuda_env_mock_set_result`,
+//! `CUDA_SUCCESS` by default, if its first result is an `i32`, and
+//! zeros otherwise.
+//!
+//! The device queries, e.g. `cuDeviceTotalMem`, answer for a single
+//! mock device, whose properties can be set, e.g. with
+//! `cuda_env_mock_set_total_mem`.
+//!
+//! Everything else, i.e. the allow list, the deny list, the
+//! capabilities and the last error, works as with a real
+//! environment.
+//!
+//! A mock environment has no `CudaEnv`. The names and types of the
+//! cuda imports are read once per process from `add_cuda_to_import`,
+//! see `cuda_import_types`, whose imports are never called.
+
+use super::driver::{
+    CU_DEVICE_ATTRIBUTE_CLOCK_RATE, CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR,
+    CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR, CU_DEVICE_ATTRIBUTE_COOPERATIVE_LAUNCH,
+    CU_DEVICE_ATTRIBUTE_GPU_OVERLAP, CU_DEVICE_ATTRIBUTE_MAX_BLOCKS_PER_MULTIPROCESSOR,
+    CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_X, CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Y,
+    CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Z, CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_X,
+    CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Y, CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Z,
+    CU_DEVICE_ATTRIBUTE_MAX_PITCH, CU_DEVICE_ATTRIBUTE_MAX_REGISTERS_PER_BLOCK,
+    CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK,
+    CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_MULTIPROCESSOR,
+    CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK, CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_MULTIPROCESSOR,
+    CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT, CU_DEVICE_ATTRIBUTE_TEXTURE_ALIGNMENT,
+    CU_DEVICE_ATTRIBUTE_TEXTURE_PITCH_ALIGNMENT, CU_DEVICE_ATTRIBUTE_TOTAL_CONSTANT_MEMORY,
+    CU_DEVICE_ATTRIBUTE_WARP_SIZE,
+};
+use super::{cuda_env_t, cuda_results, CudaEnvShared};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::{Arc, Mutex, RwLock};
+use wasmer_api::{Extern, ExternType, Function, Store, Val};
+
+/// `CUDA_SUCCESS`, the default result of the mock cuda imports.
+const CUDA_SUCCESS: i32 = 0;
+
+/// A call to a cuda import of a mock environment.
+#[derive(Clone)]
+pub struct CudaCall {
+    pub namespace: String,
+    pub name: String,
+    pub args: Vec<Val>,
+}
+
+/// The properties of the mock device.
+#[derive(Clone)]
+pub(super) struct MockDevice {
+    pub(super) name: String,
+    pub(super) total_mem: u64,
+    /// the free memory, all of it if `None`
+    pub(super) free_mem: Option<u64>,
+    /// `(major, minor)`
+    pub(super) compute_capability: (i32, i32),
+    /// the memory allocated by the guests of the environment, see
+    /// `cuda_env_set_memory_limit`
+    pub(super) used_mem: u64,
+}
+
+impl Default for MockDevice {
+    fn default() -> Self {
+        Self {
+            name: "Wasmer Mock GPU".to_string(),
+            total_mem: 16 << 30,
+            free_mem: None,
+            compute_capability: (8, 0),
+            used_mem: 0,
+        }
+    }
+}
+
+impl MockDevice {
+    /// the `CUdevice_attribute` `attribute`, those of an A100 but for
+    /// the compute capability, or 0 for the other attributes. Like the
+    /// real devices, it supports cooperative launches from 6.0.
+    pub(super) fn attribute(&self, attribute: i32) -> i32 {
+        match attribute {
+            CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR => self.compute_capability.0,
+            CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR => self.compute_capability.1,
+            CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK => 1024,
+            CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_X | CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Y => 1024,
+            CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Z => 64,
+            CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_X => i32::MAX,
+            CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Y | CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Z => 65535,
+            CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK => 48 << 10,
+            CU_DEVICE_ATTRIBUTE_TOTAL_CONSTANT_MEMORY => 64 << 10,
+            CU_DEVICE_ATTRIBUTE_WARP_SIZE => 32,
+            CU_DEVICE_ATTRIBUTE_MAX_PITCH => i32::MAX,
+            CU_DEVICE_ATTRIBUTE_MAX_REGISTERS_PER_BLOCK => 64 << 10,
+            CU_DEVICE_ATTRIBUTE_CLOCK_RATE => 1_410_000,
+            CU_DEVICE_ATTRIBUTE_TEXTURE_ALIGNMENT => 512,
+            CU_DEVICE_ATTRIBUTE_GPU_OVERLAP => 1,
+            CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT => 108,
+            CU_DEVICE_ATTRIBUTE_TEXTURE_PITCH_ALIGNMENT => 32,
+            CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_MULTIPROCESSOR => 2048,
+            CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_MULTIPROCESSOR => 164 << 10,
+            CU_DEVICE_ATTRIBUTE_COOPERATIVE_LAUNCH => (self.compute_capability >= (6, 0)) as i32,
+            CU_DEVICE_ATTRIBUTE_MAX_BLOCKS_PER_MULTIPROCESSOR => 32,
+            _ => 0,
+        }
+    }
+
+    /// the number of blocks of `block_size` threads and `shared_mem`
+    /// bytes of shared memory that fit at once on a multiprocessor,
+    /// limited by its threads, blocks and shared memory only, since the
+    /// mock kernels use no registers
+    pub(super) fn occupancy(&self, block_size: u32, shared_mem: usize) -> i32 {
+        let limit = |attribute, per_block: usize| match per_block {
+            0 => i32::MAX,
+            per_block => (self.attribute(attribute) as usize / per_block) as i32,
+        };
+
+        let by_threads = limit(
+            CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_MULTIPROCESSOR,
+            block_size as usize,
+        );
+        let by_shared_mem = limit(
+            CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_MULTIPROCESSOR,
+            shared_mem,
+        );
+        let max_blocks = self.attribute(CU_DEVICE_ATTRIBUTE_MAX_BLOCKS_PER_MULTIPROCESSOR);
+
+        by_threads.min(by_shared_mem).min(max_blocks)
+    }
+}
+
+#[derive(Default)]
+pub(super) struct CudaMock {
+    calls: Mutex<Vec<CudaCall>>,
+    /// the results returned by the imports, by name
+    results: RwLock<HashMap<String, i32>>,
+    device: RwLock<MockDevice>,
+}
+
+impl CudaMock {
+    /// the number of mock devices
+    pub(super) fn device_count(&self) -> i32 {
+        1
+    }
+
+    pub(super) fn device(&self) -> MockDevice {
+        self.device.read().unwrap().clone()
+    }
+
+    /// a mock of every function of `import_types`, the non-function
+    /// imports can't be mocked and are left out
+    pub(super) fn externs(
+        mock: &Arc<Self>,
+        store: &Store,
+        import_types: &[(String, String, ExternType)],
+    ) -> Vec<(String, String, Extern)> {
+        import_types
+            .iter()
+            .filter_map(|(namespace, name, ty)| {
+                let function_type = match ty.func() {
+                    Some(function_type) => function_type.clone(),
+                    None => {
+                        log::debug!("cannot mock the cuda import {}::{}", namespace, name);
+                        return None;
+                    }
+                };
+                let mock = mock.clone();
+                let call_namespace = namespace.clone();
+                let call_name = name.clone();
+                let results = function_type.results().to_vec();
+
+                let function = Function::new(store, function_type, move |args| {
+                    log::debug!("mock call to {}::{}{:?}", call_namespace, call_name, args);
+                    mock.calls.lock().unwrap().push(CudaCall {
+                        namespace: call_namespace.clone(),
+                        name: call_name.clone(),
+                        args: args.to_vec(),
+                    });
+                    let result = mock
+                        .results
+                        .read()
+                        .unwrap()
+                        .get(&call_name)
+                        .copied()
+                        .unwrap_or(CUDA_SUCCESS);
+
+                    Ok(cuda_results(&results, result))
+                });
+
+                Some((namespace.clone(), name.clone(), Extern::from(function)))
+            })
+            .collect()
+    }
+}
+
+impl cuda_env_t {
+    /// The calls made to the cuda imports of this environment, in
+    /// order, or `None` if it isn't a mock environment.
+    pub fn mock_calls(&self) -> Option<Vec<CudaCall>> {
+        let mock = self.shared.mock.as_ref()?;
+        let calls = mock.calls.lock().unwrap().clone();
+
+        Some(calls)
+    }
+
+    fn mock(&self) -> Option<&CudaMock> {
+        match self.shared.mock.as_deref() {
+            Some(mock) => Some(mock),
+            None => {
+                crate::error::update_last_error("this cuda_env_t isn't a mock environment");
+                None
+            }
+        }
+    }
+}
+
+/// Create a new mock CUDA environment, whose cuda imports record their
+/// calls instead of calling CUDA, so that no GPU is needed.
+///
+/// The returned handle must be deleted with `cuda_env_delete`.
+#[no_mangle]
+pub extern "C" fn cuda_env_new_mock() -> *mut cuda_env_t {
+    cuda_env_t {
+        shared: Arc::new(CudaEnvShared::new(Some(Arc::default()))),
+    }
+    .into_handle()
+}
+
+/// Make the cuda import `name` of the mock environment `cuda_env`
+/// return `result`, e.g. a `CUresult` error code, instead of
+/// `CUDA_SUCCESS`.
+///
+/// It applies to the imports already built too. Returns false, and
+/// sets the last error, if `cuda_env` isn't a mock environment.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_env_mock_set_result(
+    cuda_env: &cuda_env_t,
+    name: *const c_char,
+    result: i32,
+) -> bool {
+    debug_assert!(!name.is_null());
+
+    let mock = match cuda_env.mock() {
+        Some(mock) => mock,
+        None => return false,
+    };
+    let name = c_try!(CStr::from_ptr(name).to_str(); otherwise false);
+    mock.results
+        .write()
+        .unwrap()
+        .insert(name.to_string(), result);
+
+    true
+}
+
+/// Count the calls made to the cuda import `name` of the mock
+/// environment `cuda_env`, or to any of its cuda imports if `name` is
+/// `NULL`.
+///
+/// Returns 0, and sets the last error, if `cuda_env` isn't a mock
+/// environment.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_env_mock_call_count(
+    cuda_env: &cuda_env_t,
+    name: *const c_char,
+) -> usize {
+    let mock = match cuda_env.mock() {
+        Some(mock) => mock,
+        None => return 0,
+    };
+    let calls = mock.calls.lock().unwrap();
+
+    if name.is_null() {
+        return calls.len();
+    }
+    let name = CStr::from_ptr(name).to_string_lossy();
+
+    calls.iter().filter(|call| call.name == name).count()
+}
+
+/// Set the total memory of the mock device of `cuda_env`, in bytes,
+/// as returned by `cuDeviceTotalMem`. It is 16 GiB by default.
+///
+/// Returns false, and sets the last error, if `cuda_env` isn't a mock
+/// environment.
+#[no_mangle]
+pub extern "C" fn cuda_env_mock_set_total_mem(cuda_env: &cuda_env_t, bytes: u64) -> bool {
+    let mock = match cuda_env.mock() {
+        Some(mock) => mock,
+        None => return false,
+    };
+    mock.device.write().unwrap().total_mem = bytes;
+
+    true
+}
+
+/// Set the free memory of the mock device of `cuda_env`, in bytes, as
+/// returned by `cuMemGetInfo`. It is clamped to the total memory, and
+/// is all of it by default.
+///
+/// Returns false, and sets the last error, if `cuda_env` isn't a mock
+/// environment.
+#[no_mangle]
+pub extern "C" fn cuda_env_mock_set_free_mem(cuda_env: &cuda_env_t, bytes: u64) -> bool {
+    let mock = match cuda_env.mock() {
+        Some(mock) => mock,
+        None => return false,
+    };
+    mock.device.write().unwrap().free_mem = Some(bytes);
+
+    true
+}
+
+/// Set the compute capability of the mock device of `cuda_env`, as
+/// returned by `cuDeviceComputeCapability`, to simulate different
+/// architectures. It is 8.0 by default.
+///
+/// Returns false, and sets the last error, if `cuda_env` isn't a mock
+/// environment.
+#[no_mangle]
+pub extern "C" fn cuda_env_mock_set_compute_capability(
+    cuda_env: &cuda_env_t,
+    major: i32,
+    minor: i32,
+) -> bool {
+    let mock = match cuda_env.mock() {
+        Some(mock) => mock,
+        None => return false,
+    };
+    mock.device.write().unwrap().compute_capability = (major, minor);
+
+    true
+}
+
+/// Set the device memory allocated by the guests of the mock
+/// environment `cuda_env`, in bytes, which is subtracted from its
+/// memory limit by `cuMemGetInfo`, see `cuda_env_set_memory_limit`. It
+/// is 0 by default.
+///
+/// Returns false, and sets the last error, if `cuda_env` isn't a mock
+/// environment.
+#[no_mangle]
+pub extern "C" fn cuda_env_mock_set_memory_usage(cuda_env: &cuda_env_t, bytes: u64) -> bool {
+    let mock = match cuda_env.mock() {
+        Some(mock) => mock,
+        None => return false,
+    };
+    mock.device.write().unwrap().used_mem = bytes;
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(all(feature = "wat", feature = "wasi"))]
+    #[test]
+    fn test_cuda_env_new_mock() {
+        use super::super::tests::{args_of, wat_types};
+        use super::super::wasi::cuda_wasi_get_imports;
+        use super::super::{
+            cuda_env_delete, cuda_get_imports, cuda_import_types, cuda_imports_error_t,
+        };
+        use super::{cuda_env_mock_call_count, cuda_env_mock_set_result, cuda_env_new_mock};
+        use crate::wasm_c_api::engine::wasm_engine_new;
+        use crate::wasm_c_api::externals::wasm_extern_vec_t;
+        use crate::wasm_c_api::instance::wasm_instance_new;
+        use crate::wasm_c_api::module::wasm_module_new;
+        use crate::wasm_c_api::store::wasm_store_new;
+        use crate::wasm_c_api::types::wasm_byte_vec_t;
+        use crate::wasm_c_api::wasi::{wasi_config_new, wasi_env_new};
+        use std::ffi::CString;
+        use std::ptr;
+        use wasmer_api::{wat2wasm, Type};
+
+        unsafe {
+            let engine = wasm_engine_new();
+            let store = wasm_store_new(Some(&engine)).unwrap();
+            let handle = cuda_env_new_mock();
+            let cuda_env = &*handle;
+            assert!(cuda_env.shared.inner.is_none());
+
+            // every cuda import is registered, with its real type
+            let import_types = cuda_import_types(&store.inner);
+            let functions = import_types
+                .iter()
+                .filter_map(|(namespace, name, ty)| Some((namespace, name, ty.func()?)))
+                .collect::<Vec<_>>();
+            let wat = functions
+                .iter()
+                .map(|(namespace, name, ty)| {
+                    format!(
+                        r#"(import "{}" "{}" (func (param{}) (result{})))"#,
+                        namespace,
+                        name,
+                        wat_types(ty.params()),
+                        wat_types(ty.results()),
+                    )
+                })
+                .collect::<String>();
+            let wasm: wasm_byte_vec_t = wat2wasm(format!("(module {})", wat).as_bytes())
+                .unwrap()
+                .into_owned()
+                .into();
+            let module = wasm_module_new(Some(&store), Some(&wasm)).unwrap();
+            let mut imports: wasm_extern_vec_t = Vec::new().into();
+            assert_eq!(
+                cuda_get_imports(
+                    Some(&store),
+                    Some(&module),
+                    Some(cuda_env),
+                    Some(&mut imports)
+                ),
+                cuda_imports_error_t::CUDA_IMPORTS_OK
+            );
+            assert_eq!(imports.as_slice().len(), functions.len());
+
+            // the whole WASI path works without a GPU
+            let (namespace, name, ty) = functions[0];
+            let wasm: wasm_byte_vec_t = wat2wasm(
+                format!(
+                    r#"(module
+                      (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+                      (import "{}" "{}" (func $cuda (param{}) (result{})))
+                      (memory (export "memory") 1)
+                      (export "cuda" (func $cuda)))"#,
+                    namespace,
+                    name,
+                    wat_types(ty.params()),
+                    wat_types(ty.results()),
+                )
+                .as_bytes(),
+            )
+            .unwrap()
+            .into_owned()
+            .into();
+            let module = wasm_module_new(Some(&store), Some(&wasm)).unwrap();
+            let wasi_env = wasi_env_new(wasi_config_new(b"mock\0".as_ptr() as _).unwrap()).unwrap();
+            let mut imports: wasm_extern_vec_t = Vec::new().into();
+            assert_eq!(
+                cuda_wasi_get_imports(
+                    Some(&store),
+                    Some(&module),
+                    Some(cuda_env),
+                    Some(&wasi_env),
+                    Some(&mut imports),
+                ),
+                cuda_imports_error_t::CUDA_IMPORTS_OK
+            );
+            assert_eq!(imports.as_slice().len(), 2);
+
+            let c_name = CString::new(name.as_str()).unwrap();
+            assert!(cuda_env_mock_set_result(cuda_env, c_name.as_ptr(), 2));
+
+            let instance =
+                wasm_instance_new(Some(&store), Some(&module), Some(&imports), None).unwrap();
+            let cuda = instance.inner.exports.get_function("cuda").unwrap();
+            let args = args_of(ty.params(), 7);
+            let results = cuda.call(&args).unwrap();
+            if let Some(Type::I32) = ty.results().first() {
+                assert_eq!(results[0].i32(), Some(2));
+            }
+
+            assert_eq!(cuda_env_mock_call_count(cuda_env, c_name.as_ptr()), 1);
+            assert_eq!(cuda_env_mock_call_count(cuda_env, ptr::null()), 1);
+            let calls = cuda_env.mock_calls().unwrap();
+            assert_eq!(calls.len(), 1);
+            assert_eq!(&calls[0].namespace, namespace);
+            assert_eq!(&calls[0].name, name);
+            assert!(calls[0].args == args);
+
+            cuda_env_delete(handle);
+        }
+    }
+
+    #[test]
+    fn test_cuda_env_mock_set_result_not_mock() {
+        use super::super::{cuda_env_delete, cuda_env_new};
+        use super::{cuda_env_mock_call_count, cuda_env_mock_set_result};
+        use std::ptr;
+
+        unsafe {
+            let handle = cuda_env_new();
+            assert!(!cuda_env_mock_set_result(
+                &*handle,
+                b"cuInit\0".as_ptr() as _,
+                0
+            ));
+            assert_eq!(cuda_env_mock_call_count(&*handle, ptr::null()), 0);
+            assert!((*handle).mock_calls().is_none());
+            cuda_env_delete(handle);
+        }
+    }
+}
@@ -0,0 +1,2278 @@
+use wasmer_cuda::CudaEnv;
+use wasmer_cuda::add_cuda_to_import;
+use crate::error::update_last_error;
+use crate::wasm_c_api::store::wasm_store_t;
+use crate::wasm_c_api::module::wasm_module_t;
+use crate::wasm_c_api::externals::wasm_extern_vec_t;
+use crate::wasm_c_api::instance::{wasm_instance_new, wasm_instance_t};
+use crate::wasm_c_api::trap::wasm_trap_t;
+use crate::wasm_c_api::types::wasm_byte_vec_t;
+#[cfg(feature = "compiler")]
+use crate::wasm_c_api::engine::{wasm_config_t, wasmer_compiler_t};
+use wasmer_api::{
+    imports, Exports, Extern, ExternType, Function, FunctionType, ImportObject, Module,
+    NamedResolver, Store, Type, Val,
+};
+use lazy_static::lazy_static;
+use std::collections::{HashMap, HashSet};
+use std::any::Any;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::AtomicU32;
+use std::sync::{Arc, Mutex, RwLock};
+use thiserror::Error;
+
+pub mod capabilities;
+pub mod config;
+pub mod device;
+mod driver;
+mod error_strings;
+mod last_error;
+pub mod launch;
+pub mod mock;
+pub mod nvtx;
+mod supported;
+#[cfg(feature = "wasi")]
+pub mod wasi;
+
+#[allow(non_camel_case_types)]
+pub struct cuda_env_t {
+    /// shared with the handles made by `cuda_env_clone`
+    shared: Arc<CudaEnvShared>,
+}
+
+struct CudaEnvShared {
+    /// the environment of the cuda imports, `None` for the mock
+    /// environments, which never call CUDA
+    inner: Option<CudaEnv>,
+    /// if not empty, only these cuda imports are registered
+    allowed_imports: RwLock<HashSet<String>>,
+    /// cuda imports that are never registered
+    denied_imports: RwLock<HashSet<String>>,
+    /// the last failing call, see `cuda_env_last_error`
+    last_error: Mutex<Option<String>>,
+    /// the `cuda_capability_t` mask, see `cuda_env_set_capabilities`
+    capabilities: AtomicU32,
+    /// set for the environments made by `cuda_env_new_mock`, whose cuda
+    /// imports don't call `inner`
+    mock: Option<Arc<mock::CudaMock>>,
+    /// the total memory of each device, see `cuDeviceTotalMem`
+    device_total_mem: Mutex<HashMap<i32, u64>>,
+    /// the compute capability of each device, see
+    /// `cuDeviceComputeCapability`
+    compute_capabilities: Mutex<HashMap<i32, (i32, i32)>>,
+    /// the device memory of the guests, see `cuda_env_set_memory_limit`
+    memory_limit: Mutex<Option<u64>>,
+}
+
+impl CudaEnvShared {
+    fn new(mock: Option<Arc<mock::CudaMock>>) -> Self {
+        Self {
+            inner: match mock {
+                Some(_) => None,
+                None => Some(CudaEnv::default()),
+            },
+            allowed_imports: RwLock::new(HashSet::new()),
+            denied_imports: RwLock::new(HashSet::new()),
+            last_error: Mutex::new(None),
+            capabilities: AtomicU32::new(capabilities::CUDA_CAPABILITIES_ALL),
+            mock,
+            device_total_mem: Mutex::new(HashMap::new()),
+            compute_capabilities: Mutex::new(HashMap::new()),
+            memory_limit: Mutex::new(None),
+        }
+    }
+
+    /// record that `call` failed with the CUDA error `result`
+    fn record_error(&self, call: &str, result: i32) {
+        let message = format!("{} failed with CUDA error {}", call, result);
+        log::debug!("{}", message);
+
+        *self.last_error.lock().unwrap() = Some(message);
+    }
+}
+
+/// the imports added by the C API to each namespace of the cuda imports
+const HELPER_IMPORTS: &[&str] = &[
+    last_error::LAST_ERROR_STRING_NAME,
+    error_strings::ERROR_NAME_NAME,
+    error_strings::ERROR_STRING_NAME,
+];
+
+impl cuda_env_t {
+    /// turn `self` into a handle owned by C, to be released with `cuda_env_delete`
+    fn into_handle(self) -> *mut cuda_env_t {
+        Arc::into_raw(Arc::new(self)) as *mut cuda_env_t
+    }
+
+    /// another reference on the handle `self`, which, like every
+    /// `cuda_env_t`, comes from `into_handle`
+    fn shared_handle(&self) -> Arc<cuda_env_t> {
+        let handle = self as *const cuda_env_t;
+
+        unsafe {
+            Arc::increment_strong_count(handle);
+            Arc::from_raw(handle)
+        }
+    }
+
+    fn is_import_enabled(&self, name: &str) -> bool {
+        let allowed_imports = self.shared.allowed_imports.read().unwrap();
+
+        (allowed_imports.is_empty() || allowed_imports.contains(name))
+            && !self.shared.denied_imports.read().unwrap().contains(name)
+    }
+
+    /// add the cuda imports enabled for this env to `import_object`,
+    /// along with `cuda_supported_functions` listing them
+    pub(super) fn add_to_import(&self, store: &Store, import_object: &mut ImportObject) {
+        let cuda_externs = match (&self.shared.inner, &self.shared.mock) {
+            (Some(inner), _) => {
+                let mut cuda_import_object = imports! {};
+                add_cuda_to_import(store, inner.clone(), &mut cuda_import_object);
+                cuda_import_object.externs_vec()
+            }
+            (None, Some(mock)) => mock::CudaMock::externs(mock, store, &cuda_import_types(store)),
+            (None, None) => unreachable!("a cuda_env_t is either real or a mock"),
+        };
+        let mut externs = self.enabled_externs(cuda_externs);
+        device::add_device_functions(store, self, &mut externs);
+        capabilities::mask_externs(store, &self.shared, &mut externs);
+        last_error::add_last_error_functions(store, &self.shared, &mut externs);
+        error_strings::add_error_string_functions(store, &mut externs);
+        // the helpers follow the allow list and the deny list too
+        let mut externs = self.enabled_externs(externs);
+        device::add_runtime_functions(store, self, &mut externs);
+
+        let mut nvtx_import_object = imports! {};
+        nvtx::add_nvtx_to_import(store, &mut nvtx_import_object);
+        externs.extend(self.enabled_externs(nvtx_import_object.externs_vec()));
+
+        // last, so that it lists everything registered above
+        if self.is_import_enabled(supported::SUPPORTED_FUNCTIONS_NAME) {
+            supported::add_supported_functions(store, &mut externs);
+        }
+
+        log::debug!(
+            "registering {} cuda imports ({} allowed, {} denied)",
+            externs.len(),
+            self.shared.allowed_imports.read().unwrap().len(),
+            self.shared.denied_imports.read().unwrap().len(),
+        );
+        merge_imports(import_object, externs);
+    }
+
+    /// the `(namespace, name)` pairs of the imports registered by
+    /// `add_to_import`, found from the cached import table instead of
+    /// building the imports
+    fn import_names(&self, store: &Store) -> HashSet<(String, String)> {
+        let mut names = cuda_import_types(store)
+            .iter()
+            .filter(|(_, name, _)| self.is_import_enabled(name))
+            .map(|(namespace, name, _)| (namespace.clone(), name.clone()))
+            .collect::<HashSet<_>>();
+
+        let namespaces = names
+            .iter()
+            .map(|(namespace, _)| namespace.clone())
+            .collect::<HashSet<_>>();
+        for namespace in namespaces {
+            for name in HELPER_IMPORTS.iter().chain(device::DEVICE_IMPORTS) {
+                if self.is_import_enabled(name) {
+                    names.insert((namespace.clone(), name.to_string()));
+                }
+            }
+        }
+
+        if self.is_import_enabled(device::DEVICE_PROPERTIES_NAME) {
+            names.insert((
+                device::RUNTIME_NAMESPACE.to_string(),
+                device::DEVICE_PROPERTIES_NAME.to_string(),
+            ));
+        }
+        names.extend(
+            nvtx::NVTX_IMPORTS
+                .iter()
+                .filter(|name| self.is_import_enabled(name))
+                .map(|name| (nvtx::NVTX_NAMESPACE.to_string(), name.to_string())),
+        );
+
+        if self.is_import_enabled(supported::SUPPORTED_FUNCTIONS_NAME) {
+            let namespaces = names
+                .iter()
+                .map(|(namespace, _)| namespace.clone())
+                .collect::<HashSet<_>>();
+            for namespace in namespaces {
+                names.insert((namespace, supported::SUPPORTED_FUNCTIONS_NAME.to_string()));
+            }
+        }
+
+        names
+    }
+
+    fn enabled_externs(
+        &self,
+        externs: Vec<(String, String, Extern)>,
+    ) -> Vec<(String, String, Extern)> {
+        externs
+            .into_iter()
+            .filter(|(_, name, _)| self.is_import_enabled(name))
+            .collect()
+    }
+
+    /// Like `add_to_import`, but nothing is added if one of the cuda
+    /// imports is already registered in `import_object`. The colliding
+    /// `(namespace, name)` pairs are returned instead.
+    #[cfg(feature = "wasi")]
+    pub(super) fn add_to_import_checked(
+        &self,
+        store: &Store,
+        import_object: &mut ImportObject,
+    ) -> Result<(), Vec<(String, String)>> {
+        let mut cuda_import_object = imports! {};
+        self.add_to_import(store, &mut cuda_import_object);
+
+        merge_imports_checked(import_object, cuda_import_object.externs_vec())
+    }
+}
+
+/// Like `wasmer_cuda::add_cuda_to_import`, but nothing is added if one
+/// of the cuda imports is already registered in `import_object`, e.g.
+/// by the WASI imports or by a host function of the embedder. The
+/// colliding `(namespace, name)` pairs are returned instead.
+pub fn add_cuda_to_import_checked(
+    store: &Store,
+    cuda_env: CudaEnv,
+    import_object: &mut ImportObject,
+) -> Result<(), Vec<(String, String)>> {
+    let mut cuda_import_object = imports! {};
+    add_cuda_to_import(store, cuda_env, &mut cuda_import_object);
+
+    merge_imports_checked(import_object, cuda_import_object.externs_vec())
+}
+
+/// like `merge_imports`, but nothing is registered if one of `externs`
+/// is already in `import_object`, the colliding `(namespace, name)`
+/// pairs are returned instead
+fn merge_imports_checked(
+    import_object: &mut ImportObject,
+    externs: Vec<(String, String, Extern)>,
+) -> Result<(), Vec<(String, String)>> {
+    let collisions = externs
+        .iter()
+        .filter(|(namespace, name, _)| import_object.resolve_by_name(namespace, name).is_some())
+        .map(|(namespace, name, _)| (namespace.clone(), name.clone()))
+        .collect::<Vec<_>>();
+    if !collisions.is_empty() {
+        return Err(collisions);
+    }
+
+    merge_imports(import_object, externs);
+
+    Ok(())
+}
+
+/// add a function made by `function` as `name` to each namespace of
+/// `externs` that doesn't have `name` yet
+fn add_to_each_namespace(
+    externs: &mut Vec<(String, String, Extern)>,
+    name: &str,
+    mut function: impl FnMut() -> Function,
+) {
+    let mut namespaces: Vec<String> = Vec::new();
+    for (namespace, _, _) in externs.iter() {
+        if !namespaces.contains(namespace) {
+            namespaces.push(namespace.clone());
+        }
+    }
+    namespaces.retain(|namespace| {
+        !externs
+            .iter()
+            .any(|(ns, extern_name, _)| ns == namespace && extern_name == name)
+    });
+
+    for namespace in namespaces {
+        externs.push((namespace, name.to_string(), Extern::from(function())));
+    }
+}
+
+/// register `externs` in `import_object`, keeping what is already
+/// registered in their namespaces
+fn merge_imports(
+    import_object: &mut ImportObject,
+    externs: impl IntoIterator<Item = (String, String, Extern)>,
+) {
+    let mut namespaces: Vec<(String, Exports)> = Vec::new();
+    for (namespace, name, extern_) in externs {
+        match namespaces.iter_mut().find(|(ns, _)| *ns == namespace) {
+            Some((_, exports)) => exports.insert(name, extern_),
+            None => {
+                let mut exports = import_object
+                    .get_namespace_exports(&namespace)
+                    .unwrap_or_default();
+                exports.insert(name, extern_);
+                namespaces.push((namespace, exports));
+            }
+        }
+    }
+
+    for (namespace, exports) in namespaces {
+        import_object.register(namespace, exports);
+    }
+}
+
+/// Status codes returned by `cuda_get_imports` and
+/// `cuda_wasi_get_imports`.
+///
+/// Whatever the code, a descriptive message is also available
+/// through the last error API when the call fails.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub enum cuda_imports_error_t {
+    /// The imports have been built.
+    CUDA_IMPORTS_OK = 0,
+
+    /// A required argument is `NULL`.
+    CUDA_IMPORTS_NULL_ARG = 1,
+
+    /// The module doesn't import any known WASI version.
+    CUDA_IMPORTS_NO_WASI_VERSION = 2,
+
+    /// The module requires imports that neither the cuda imports nor
+    /// the WASI imports provide. The last error lists all of them.
+    CUDA_IMPORTS_UNRESOLVED_IMPORT = 3,
+
+    /// Building the imports failed unexpectedly, e.g. the CUDA
+    /// environment panicked.
+    CUDA_IMPORTS_INTERNAL = 4,
+
+    /// Some cuda imports are already registered by the WASI imports.
+    /// Only returned by `cuda_wasi_get_imports_strict`.
+    CUDA_IMPORTS_COLLISION = 5,
+}
+
+/// Errors raised by the CUDA part of the C API.
+///
+/// The C functions report them through the last error API, Rust
+/// callers can match on the kind of failure.
+#[derive(Debug, Clone, Error)]
+pub enum CudaError {
+    /// A required argument is `NULL`.
+    #[error("the `{0}` argument is NULL")]
+    NullArgument(&'static str),
+    /// The module doesn't import any known WASI version.
+    #[error("could not detect a WASI version on this module")]
+    NoWasiVersion,
+    /// The module requires imports that the import object doesn't provide.
+    #[error("Unresolved GPU imports:{}", format_unresolved_imports(.0))]
+    ImportResolution(Vec<(String, String)>),
+    /// Some cuda imports are already registered in the import object.
+    #[error("cuda imports collide with already registered imports: {}", format_imports(.0))]
+    Collision(Vec<(String, String)>),
+    /// A CUDA call failed with this `CUresult`.
+    #[error("CUDA driver error {0}")]
+    Driver(i32),
+    /// A guest buffer isn't entirely inside the guest memory.
+    #[error("guest buffer of {len} bytes at offset {offset} is out of bounds")]
+    Bounds { offset: u64, len: u64 },
+    /// No CUDA device is available.
+    #[error("no CUDA device is available")]
+    NoDevice,
+    /// Building the imports panicked.
+    #[error("internal error while building the cuda imports: {0}")]
+    Internal(String),
+}
+
+fn format_unresolved_imports(imports: &[(String, String)]) -> String {
+    imports
+        .iter()
+        .map(|(module, name)| format!("\n  {}::{}", module, name))
+        .collect()
+}
+
+fn format_imports(imports: &[(String, String)]) -> String {
+    imports
+        .iter()
+        .map(|(module, name)| format!("\"{}\" \"{}\"", module, name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl CudaError {
+    /// The status code reported to C for this error.
+    pub fn code(&self) -> cuda_imports_error_t {
+        match self {
+            Self::NullArgument(_) => cuda_imports_error_t::CUDA_IMPORTS_NULL_ARG,
+            Self::NoWasiVersion => cuda_imports_error_t::CUDA_IMPORTS_NO_WASI_VERSION,
+            Self::ImportResolution(_) => cuda_imports_error_t::CUDA_IMPORTS_UNRESOLVED_IMPORT,
+            Self::Collision(_) => cuda_imports_error_t::CUDA_IMPORTS_COLLISION,
+            Self::Driver(_) | Self::Bounds { .. } | Self::NoDevice | Self::Internal(_) => {
+                cuda_imports_error_t::CUDA_IMPORTS_INTERNAL
+            }
+        }
+    }
+
+    /// The `CUresult` returned to the guests for this error.
+    fn cu_result(&self) -> i32 {
+        match self {
+            Self::Driver(code) => *code,
+            Self::Bounds { .. } => driver::CUDA_ERROR_INVALID_VALUE,
+            Self::NoDevice => driver::CUDA_ERROR_NO_DEVICE,
+            _ => driver::CUDA_ERROR_UNKNOWN,
+        }
+    }
+
+    fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "unknown panic".to_string(),
+            },
+        };
+
+        Self::Internal(message)
+    }
+}
+
+/// Run `build`, store its error as the last error, and turn the
+/// outcome into a `cuda_imports_error_t` code. A panic is reported
+/// as `CUDA_IMPORTS_INTERNAL` instead of unwinding into C.
+pub(super) fn imports_status(
+    build: impl FnOnce() -> Result<(), CudaError>,
+) -> cuda_imports_error_t {
+    let result = panic::catch_unwind(AssertUnwindSafe(build))
+        .unwrap_or_else(|payload| Err(CudaError::from_panic(payload)));
+
+    match result {
+        Ok(()) => cuda_imports_error_t::CUDA_IMPORTS_OK,
+        Err(error) => {
+            if let CudaError::Internal(_) = error {
+                log::warn!("failed to build the cuda imports: {}", error);
+            } else {
+                log::debug!("failed to build the cuda imports: {}", error);
+            }
+            let code = error.code();
+            update_last_error(error);
+            code
+        }
+    }
+}
+
+/// Create a new CUDA environment
+///
+/// The returned handle must be deleted with `cuda_env_delete`.
+#[no_mangle]
+pub extern "C" fn cuda_env_new() -> *mut cuda_env_t {
+    cuda_env_t {
+        shared: Arc::new(CudaEnvShared::new(None)),
+    }
+    .into_handle()
+}
+
+/// Create a new handle on the CUDA environment of `cuda_env`.
+///
+/// Both handles share the same environment, including its allow list
+/// and deny list, and each of them must be deleted with
+/// `cuda_env_delete`. The environment itself is dropped exactly once,
+/// with the last handle. Returns `NULL` if `cuda_env` is `NULL`.
+#[no_mangle]
+pub extern "C" fn cuda_env_clone(cuda_env: Option<&cuda_env_t>) -> *mut cuda_env_t {
+    match cuda_env {
+        Some(cuda_env) => cuda_env_t {
+            shared: cuda_env.shared.clone(),
+        }
+        .into_handle(),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Only register the cuda import `name` (and the other allowed ones)
+/// for this environment.
+///
+/// By default every cuda import is enabled. Once at least one import
+/// is allowed, the imports that are not allowed are not placed in the
+/// import object, so a module that requires them fails to resolve.
+/// This covers the imports added by the C API too, e.g.
+/// `cuda_supported_functions` or `nvtxRangePushA`.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_env_allow_import(cuda_env: &cuda_env_t, name: *const c_char) -> bool {
+    debug_assert!(!name.is_null());
+
+    let name = c_try!(CStr::from_ptr(name).to_str(); otherwise false);
+    cuda_env
+        .shared
+        .allowed_imports
+        .write()
+        .unwrap()
+        .insert(name.to_string());
+
+    true
+}
+
+/// Never register the cuda import `name` for this environment.
+///
+/// A denied import is not placed in the import object, so a module
+/// that requires it fails to resolve. The deny list wins over the
+/// allow list. Like the allow list, it covers the imports added by
+/// the C API too, e.g. `cuda_error_name`.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_env_deny_import(cuda_env: &cuda_env_t, name: *const c_char) -> bool {
+    debug_assert!(!name.is_null());
+
+    let name = c_try!(CStr::from_ptr(name).to_str(); otherwise false);
+    cuda_env
+        .shared
+        .denied_imports
+        .write()
+        .unwrap()
+        .insert(name.to_string());
+
+    true
+}
+
+/// Get the last error recorded on `cuda_env`, as a readable message
+/// naming the failing call and its CUDA error code.
+///
+/// Unlike `wasmer_last_error_message`, which holds the last error of
+/// the whole thread, the message is only overwritten by a later
+/// failing call made through this environment (or through a handle
+/// made by `cuda_env_clone`), whatever the thread it is made from.
+/// The calls recorded are the ones answered by the C API itself,
+/// i.e. the stubs of `cuda_get_imports_with_fallbacks`, the imports
+/// outside the capabilities of the environment, and the device
+/// queries, e.g. `cuMemGetInfo`.
+///
+/// The message, which isn't nul-terminated, is written to `out`,
+/// which must then be deleted with `wasm_byte_vec_delete`. Returns
+/// false, and writes an empty vector, if no error has been recorded.
+/// Guests can read the same message through the
+/// `cuGetLastErrorString(buf, len)` import.
+#[no_mangle]
+pub extern "C" fn cuda_env_last_error(
+    cuda_env: Option<&cuda_env_t>,
+    out: &mut wasm_byte_vec_t,
+) -> bool {
+    let last_error =
+        cuda_env.and_then(|cuda_env| cuda_env.shared.last_error.lock().unwrap().clone());
+
+    match last_error {
+        Some(message) => {
+            out.set_buffer(message.into_bytes());
+            true
+        }
+        None => {
+            out.set_buffer(Vec::new());
+            false
+        }
+    }
+}
+
+/// Delete a `cuda_env_t`
+///
+/// Passing `NULL` is a no-op, so cleanup paths can call it
+/// unconditionally. Like the other `*_delete` functions, a handle
+/// must be deleted only once: using or deleting it afterwards is
+/// undefined behavior.
+///
+/// The environment itself is dropped once its last handle, from
+/// `cuda_env_new` or `cuda_env_clone`, is deleted, and the imports
+/// built from it, e.g. by `cuda_get_imports`, are dropped.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_env_delete(cuda_env: *mut cuda_env_t) {
+    if cuda_env.is_null() {
+        return;
+    }
+
+    drop(Arc::from_raw(cuda_env as *const cuda_env_t));
+}
+
+/// Create a new instance like `wasm_instance_new`, and bind
+/// `cuda_env` to it, so that it can be retrieved later with
+/// `wasm_instance_get_cuda_env`.
+///
+/// `imports` is expected to be built by `cuda_get_imports` or
+/// `cuda_wasi_get_imports` from the same `cuda_env`. The instance
+/// holds a reference on the `cuda_env` handle, so the caller still
+/// deletes it as usual.
+///
+/// `wasm_instance_new` can't bind the environment itself: a
+/// `wasm_extern_vec_t` doesn't record which `cuda_env_t` its externs
+/// were built from.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_instance_new(
+    store: Option<&wasm_store_t>,
+    module: Option<&wasm_module_t>,
+    imports: Option<&wasm_extern_vec_t>,
+    cuda_env: Option<&cuda_env_t>,
+    trap: Option<&mut *mut wasm_trap_t>,
+) -> Option<Box<wasm_instance_t>> {
+    let cuda_env = cuda_env?;
+
+    let mut instance = wasm_instance_new(store, module, imports, trap)?;
+    instance.cuda_env = Some(cuda_env.shared_handle());
+
+    Some(instance)
+}
+
+/// Get the `cuda_env_t` bound to `instance` by `cuda_instance_new`,
+/// or `NULL` if the instance has none.
+///
+/// **Only `cuda_instance_new` binds an environment.** This always
+/// returns `NULL` for an instance created by `wasm_instance_new`, even
+/// if its imports were built by `cuda_get_imports` or
+/// `cuda_wasi_get_imports`.
+///
+/// This is the very pointer given to `cuda_instance_new`. It stays
+/// valid as long as the instance, even once the caller deleted its
+/// handle, but it isn't owned by the caller: it must not be deleted
+/// with `cuda_env_delete`.
+#[no_mangle]
+pub extern "C" fn wasm_instance_get_cuda_env(
+    instance: Option<&wasm_instance_t>,
+) -> Option<&cuda_env_t> {
+    instance?.cuda_env.as_deref()
+}
+
+/// Fill `imports` with the imports of `module`, in the order the
+/// module declares them, resolved against the cuda imports of
+/// `cuda_env`.
+///
+/// The imports share the ownership of the environment: those added by
+/// the C API, and the cuda imports of a mock environment, hold a
+/// reference on its state, and the other cuda imports their own clone
+/// of its `CudaEnv`. So `cuda_env` can be deleted as soon as the
+/// imports are built.
+///
+/// Instantiate the module with `cuda_instance_new`, rather than
+/// `wasm_instance_new`, to retrieve `cuda_env` from the instance later
+/// with `wasm_instance_get_cuda_env`.
+///
+/// Returns `CUDA_IMPORTS_OK` on success. On failure, the reason is
+/// also available through the last error API.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_get_imports(
+    store: Option<&wasm_store_t>,
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    imports: Option<&mut wasm_extern_vec_t>,
+) -> cuda_imports_error_t {
+    imports_status(|| cuda_get_imports_inner(store, module, cuda_env, imports))
+}
+
+/// Like `cuda_get_imports`, but only tells whether it succeeded.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_get_imports_bool(
+    store: Option<&wasm_store_t>,
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    imports: Option<&mut wasm_extern_vec_t>,
+) -> bool {
+    cuda_get_imports(store, module, cuda_env, imports) == cuda_imports_error_t::CUDA_IMPORTS_OK
+}
+
+fn cuda_get_imports_inner(
+    store: Option<&wasm_store_t>,
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    imports: Option<&mut wasm_extern_vec_t>,
+) -> Result<(), CudaError> {
+    let store = store.ok_or(CudaError::NullArgument("store"))?;
+    let module = module.ok_or(CudaError::NullArgument("module"))?;
+    let cuda_env = cuda_env.ok_or(CudaError::NullArgument("cuda_env"))?;
+    let imports = imports.ok_or(CudaError::NullArgument("imports"))?;
+
+    let store = &store.inner;
+
+    let mut import_object = imports! {};
+    cuda_env.add_to_import(store, &mut import_object);
+
+    map_to_ordered_imports(imports, module, import_object, store)
+}
+
+/// resolve every import of `module` with `resolve`, in the order the
+/// module declares them, along with the `(module, name)` pairs of
+/// every import that cannot be resolved
+fn resolve_imports<T>(
+    module: &wasm_module_t,
+    mut resolve: impl FnMut(&str, &str) -> Option<T>,
+) -> (Vec<T>, Vec<(String, String)>) {
+    let mut exports = Vec::new();
+    let mut unresolved = Vec::new();
+
+    for import_type in module.inner.imports() {
+        match resolve(import_type.module(), import_type.name()) {
+            Some(export) => exports.push(export),
+            None => unresolved.push((
+                import_type.module().to_string(),
+                import_type.name().to_string(),
+            )),
+        }
+    }
+
+    (exports, unresolved)
+}
+
+fn map_to_ordered_imports(
+    imports: &mut wasm_extern_vec_t,
+    module: &wasm_module_t,
+    import_object: ImportObject,
+    store: &Store,
+) -> Result<(), CudaError> {
+    let (exports, unresolved) = resolve_imports(module, |namespace, name| {
+        import_object.resolve_by_name(namespace, name)
+    });
+    if !unresolved.is_empty() {
+        return Err(CudaError::ImportResolution(unresolved));
+    }
+
+    imports.set_buffer(
+        exports
+            .into_iter()
+            .map(|export| Some(Box::new(Extern::from_vm_export(store, export).into())))
+            .collect(),
+    );
+
+    Ok(())
+}
+
+/// `cudaErrorNotSupported`, returned by the stubs registered by
+/// `cuda_get_imports_with_fallbacks`.
+const CUDA_ERROR_NOT_SUPPORTED: i32 = 801;
+
+/// `CUDA_ERROR_NOT_PERMITTED`, returned by the cuda imports outside the
+/// capabilities of their `cuda_env_t`, see `cuda_env_set_capabilities`.
+const CUDA_ERROR_NOT_PERMITTED: i32 = 800;
+
+/// Like `cuda_get_imports`, but every function import of `module`
+/// that the cuda imports don't provide is replaced by a stub. A stub
+/// logs a warning naming the import, records the failure as the last
+/// error of `cuda_env` (see `cuda_env_last_error`), and returns
+/// `cudaErrorNotSupported` (801) if its first result is an `i32`, and
+/// zeros otherwise.
+///
+/// This is a debugging mode, to partially run a module built against
+/// a newer import table than the one provided here. It must not be
+/// used in production: a module can't tell a stub from the real
+/// function unless it checks the returned error code.
+///
+/// Imports that aren't functions still have to be resolved. On
+/// failure, the reason is available through the last error API.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_get_imports_with_fallbacks(
+    store: Option<&wasm_store_t>,
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    imports: Option<&mut wasm_extern_vec_t>,
+) -> bool {
+    imports_status(|| cuda_get_imports_with_fallbacks_inner(store, module, cuda_env, imports))
+        == cuda_imports_error_t::CUDA_IMPORTS_OK
+}
+
+fn cuda_get_imports_with_fallbacks_inner(
+    store: Option<&wasm_store_t>,
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    imports: Option<&mut wasm_extern_vec_t>,
+) -> Result<(), CudaError> {
+    let store = store.ok_or(CudaError::NullArgument("store"))?;
+    let module = module.ok_or(CudaError::NullArgument("module"))?;
+    let cuda_env = cuda_env.ok_or(CudaError::NullArgument("cuda_env"))?;
+    let imports = imports.ok_or(CudaError::NullArgument("imports"))?;
+
+    let store = &store.inner;
+
+    let mut import_object = imports! {};
+    cuda_env.add_to_import(store, &mut import_object);
+
+    let stubs = module
+        .inner
+        .imports()
+        .filter(|import_type| {
+            import_object
+                .resolve_by_name(import_type.module(), import_type.name())
+                .is_none()
+        })
+        .filter_map(|import_type| {
+            let function_type = import_type.ty().func()?.clone();
+            log::warn!(
+                "stubbing the missing GPU import {}::{} {:?}",
+                import_type.module(),
+                import_type.name(),
+                function_type,
+            );
+            let stub = fallback_stub(
+                store,
+                function_type,
+                format!("{}::{}", import_type.module(), import_type.name()),
+                cuda_env.shared.clone(),
+            );
+
+            Some((
+                import_type.module().to_string(),
+                import_type.name().to_string(),
+                Extern::from(stub),
+            ))
+        })
+        .collect::<Vec<_>>();
+    merge_imports(&mut import_object, stubs);
+
+    map_to_ordered_imports(imports, module, import_object, store)
+}
+
+/// a function of type `function_type` standing for the missing import
+/// `import`, recording its calls as failures in `shared`
+fn fallback_stub(
+    store: &Store,
+    function_type: FunctionType,
+    import: String,
+    shared: Arc<CudaEnvShared>,
+) -> Function {
+    error_stub(
+        store,
+        function_type,
+        import,
+        CUDA_ERROR_NOT_SUPPORTED,
+        shared,
+    )
+}
+
+/// a function of type `function_type` standing for the import
+/// `import`, which records its calls as failing with the CUDA error
+/// `error` in `shared`, and returns `error` if its first result is an
+/// `i32`, and zeros otherwise
+fn error_stub(
+    store: &Store,
+    function_type: FunctionType,
+    import: String,
+    error: i32,
+    shared: Arc<CudaEnvShared>,
+) -> Function {
+    let results = function_type.results().to_vec();
+
+    Function::new(store, function_type, move |_| {
+        log::warn!(
+            "called the stubbed GPU import {}, which fails with CUDA error {}",
+            import,
+            error
+        );
+        shared.record_error(&import, error);
+
+        Ok(cuda_results(&results, error))
+    })
+}
+
+/// the values returned by a function with the result types `results`
+/// standing for a cuda import: `code` if its first result is an `i32`,
+/// and zeros otherwise
+fn cuda_results(results: &[Type], code: i32) -> Vec<Val> {
+    results
+        .iter()
+        .enumerate()
+        .map(|(index, ty)| match ty {
+            Type::I32 if index == 0 => Val::I32(code),
+            Type::I32 => Val::I32(0),
+            Type::I64 => Val::I64(0),
+            Type::F32 => Val::F32(0.0),
+            Type::F64 => Val::F64(0.0),
+            Type::V128 => Val::V128(0),
+            Type::ExternRef => Val::null(),
+            Type::FuncRef => Val::FuncRef(None),
+        })
+        .collect()
+}
+
+/// Check, without building the imports, that every import of
+/// `module` can be resolved by the cuda imports of `cuda_env`, e.g.
+/// to reject an incompatible module when it is uploaded.
+///
+/// Only the import names are checked, against a table built once, so
+/// it is much cheaper than `cuda_get_imports`. The number of
+/// resolvable imports is written to `resolved_imports` if it isn't
+/// `NULL`, even if some imports can't be resolved.
+///
+/// Returns `CUDA_IMPORTS_OK` if every import can be resolved, and
+/// `CUDA_IMPORTS_UNRESOLVED_IMPORT` otherwise, in which case the last
+/// error lists the unresolved imports.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_validate_module(
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    resolved_imports: Option<&mut usize>,
+) -> cuda_imports_error_t {
+    imports_status(|| cuda_validate_module_inner(module, cuda_env, resolved_imports))
+}
+
+fn cuda_validate_module_inner(
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    resolved_imports: Option<&mut usize>,
+) -> Result<(), CudaError> {
+    let module = module.ok_or(CudaError::NullArgument("module"))?;
+    let cuda_env = cuda_env.ok_or(CudaError::NullArgument("cuda_env"))?;
+
+    let import_names = cuda_env.import_names(module.inner.store());
+
+    validate_imports(module, resolved_imports, |namespace, name| {
+        import_names.contains(&(namespace.to_string(), name.to_string()))
+    })
+}
+
+/// check that `resolves` accepts every import of `module`, with the
+/// same resolution as `map_to_ordered_imports`
+fn validate_imports(
+    module: &wasm_module_t,
+    resolved_imports: Option<&mut usize>,
+    mut resolves: impl FnMut(&str, &str) -> bool,
+) -> Result<(), CudaError> {
+    let (resolved, unresolved) = resolve_imports(module, |namespace, name| {
+        if resolves(namespace, name) {
+            Some(())
+        } else {
+            None
+        }
+    });
+
+    if let Some(resolved_imports) = resolved_imports {
+        *resolved_imports = resolved.len();
+    }
+
+    if !unresolved.is_empty() {
+        return Err(CudaError::ImportResolution(unresolved));
+    }
+
+    Ok(())
+}
+
+/// the `(namespace, name, type)` of every import of `add_cuda_to_import`
+type CudaImportTypes = Arc<Vec<(String, String, ExternType)>>;
+
+lazy_static! {
+    /// built once, the types don't depend on the store or the environment,
+    /// from a `CudaEnv` whose imports are never called
+    static ref CUDA_IMPORT_TYPES: Mutex<Option<CudaImportTypes>> = Mutex::new(None);
+}
+
+/// the types of the cuda imports, only registered on the first call
+fn cuda_import_types(store: &Store) -> CudaImportTypes {
+    CUDA_IMPORT_TYPES
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| {
+            let mut import_object = imports! {};
+            add_cuda_to_import(store, CudaEnv::default(), &mut import_object);
+
+            Arc::new(
+                import_object
+                    .externs_vec()
+                    .into_iter()
+                    .map(|(namespace, name, extern_)| (namespace, name, extern_.ty()))
+                    .collect(),
+            )
+        })
+        .clone()
+}
+
+/// the namespaces holding the cuda imports
+fn cuda_namespaces(store: &Store) -> HashSet<String> {
+    cuda_import_types(store)
+        .iter()
+        .map(|(namespace, _, _)| namespace.clone())
+        .collect()
+}
+
+fn module_uses_cuda(module: &Module) -> bool {
+    let namespaces = cuda_namespaces(module.store());
+
+    module
+        .imports()
+        .any(|import_type| namespaces.contains(import_type.module()))
+}
+
+/// Check whether `module` imports anything from the namespaces of the
+/// cuda imports.
+#[no_mangle]
+pub extern "C" fn wasmer_module_uses_cuda(module: Option<&wasm_module_t>) -> bool {
+    match module {
+        Some(module) => module_uses_cuda(&module.inner),
+        None => false,
+    }
+}
+
+/// Only allow the compiler named `name` (`"cranelift"`, `"llvm"` or
+/// `"singlepass"`) to compile modules using the cuda imports. It can
+/// be called several times to allow several compilers.
+///
+/// With an engine built from this configuration by a compiler that
+/// isn't allowed, `wasm_module_new` fails on modules for which
+/// `wasmer_module_uses_cuda` is true. This works around compilers
+/// known to pass wrong argument values to the cuda host functions on
+/// some targets.
+///
+/// Returns false, and sets the last error, if `name` isn't a known
+/// compiler.
+#[cfg(feature = "compiler")]
+#[no_mangle]
+pub unsafe extern "C" fn wasm_config_cuda_require_compiler(
+    config: &mut wasm_config_t,
+    name: *const c_char,
+) -> bool {
+    debug_assert!(!name.is_null());
+
+    let name = c_try!(CStr::from_ptr(name).to_str(); otherwise false);
+    let compiler = match name.to_ascii_lowercase().as_str() {
+        "cranelift" => wasmer_compiler_t::CRANELIFT,
+        "llvm" => wasmer_compiler_t::LLVM,
+        "singlepass" => wasmer_compiler_t::SINGLEPASS,
+        _ => {
+            update_last_error(format!("unknown compiler `{}`", name));
+            return false;
+        }
+    };
+    config.cuda_required_compilers.push(compiler);
+
+    true
+}
+
+/// fail if `module`, compiled by the disallowed `compiler`, uses the cuda imports
+#[cfg(feature = "compiler")]
+pub(crate) fn check_cuda_compiler(module: &Module, compiler: wasmer_compiler_t) -> Result<(), String> {
+    if module_uses_cuda(module) {
+        return Err(format!(
+            "this module uses the cuda imports, and the engine's compiler ({:?}) isn't allowed \
+             to compile it, see `wasm_config_cuda_require_compiler`",
+            compiler,
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use inline_c::{assert_c, assert_cxx};
+    #[cfg(feature = "wat")]
+    use wasmer_api::{Type, Val};
+
+    /// the WAT of `types`, e.g. `" i32 i64"`, to declare a cuda import
+    /// in the modules of the tests
+    #[cfg(feature = "wat")]
+    pub(super) fn wat_types(types: &[Type]) -> String {
+        types
+            .iter()
+            .map(|ty| match ty {
+                Type::I32 => " i32",
+                Type::I64 => " i64",
+                Type::F32 => " f32",
+                Type::F64 => " f64",
+                ty => panic!("unexpected type {:?} in a cuda import", ty),
+            })
+            .collect()
+    }
+
+    /// `value` as each of `types`, to call a cuda import
+    #[cfg(feature = "wat")]
+    pub(super) fn args_of(types: &[Type], value: i32) -> Vec<Val> {
+        types
+            .iter()
+            .map(|ty| match ty {
+                Type::I64 => Val::I64(value as i64),
+                Type::F32 => Val::F32(value as f32),
+                Type::F64 => Val::F64(value as f64),
+                _ => Val::I32(value),
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_imports_outlive_cuda_env() {
+        use super::mock::{
+            cuda_env_mock_set_result, cuda_env_mock_set_total_mem, cuda_env_new_mock,
+        };
+        use super::{cuda_env_delete, cuda_get_imports, cuda_import_types, cuda_imports_error_t};
+        use crate::wasm_c_api::engine::wasm_engine_new;
+        use crate::wasm_c_api::externals::wasm_extern_vec_t;
+        use crate::wasm_c_api::instance::wasm_instance_new;
+        use crate::wasm_c_api::module::wasm_module_new;
+        use crate::wasm_c_api::store::wasm_store_new;
+        use crate::wasm_c_api::types::wasm_byte_vec_t;
+        use std::ffi::CString;
+        use wasmer_api::wat2wasm;
+
+        unsafe {
+            let engine = wasm_engine_new();
+            let store = wasm_store_new(Some(&engine)).unwrap();
+            let handle = cuda_env_new_mock();
+            let import_types = cuda_import_types(&store.inner);
+            let (namespace, name, ty) = import_types
+                .iter()
+                .find_map(|(namespace, name, ty)| Some((namespace, name, ty.func()?.clone())))
+                .unwrap();
+            let c_name = CString::new(name.as_str()).unwrap();
+            assert!(cuda_env_mock_set_result(&*handle, c_name.as_ptr(), 2));
+            assert!(cuda_env_mock_set_total_mem(&*handle, 8 << 30));
+
+            let wasm: wasm_byte_vec_t = wat2wasm(
+                format!(
+                    r#"(module
+                      (import "{0}" "{1}" (func $cuda (param{2}) (result{3})))
+                      (import "{0}" "cuDeviceTotalMem" (func $total_mem (param i32 i32) (result i32)))
+                      (import "{0}" "cuGetLastErrorString" (func $last_error (param i32 i32) (result i32)))
+                      (memory (export "memory") 1)
+                      (export "cuda" (func $cuda))
+                      (export "total_mem" (func $total_mem))
+                      (export "last_error" (func $last_error)))"#,
+                    namespace,
+                    name,
+                    wat_types(ty.params()),
+                    wat_types(ty.results()),
+                )
+                .as_bytes(),
+            )
+            .unwrap()
+            .into_owned()
+            .into();
+            let module = wasm_module_new(Some(&store), Some(&wasm)).unwrap();
+            let mut imports: wasm_extern_vec_t = Vec::new().into();
+            assert_eq!(
+                cuda_get_imports(
+                    Some(&store),
+                    Some(&module),
+                    Some(&*handle),
+                    Some(&mut imports)
+                ),
+                cuda_imports_error_t::CUDA_IMPORTS_OK
+            );
+            let instance =
+                wasm_instance_new(Some(&store), Some(&module), Some(&imports), None).unwrap();
+
+            // the only handle and the imports are gone, the instance
+            // still works with the state of the environment
+            cuda_env_delete(handle);
+            drop(imports);
+
+            let exports = &instance.inner.exports;
+            let results = exports
+                .get_function("cuda")
+                .unwrap()
+                .call(&args_of(ty.params(), 7))
+                .unwrap();
+            if let Some(Type::I32) = ty.results().first() {
+                assert_eq!(results[0].i32(), Some(2));
+            }
+
+            let total_mem = exports.get_function("total_mem").unwrap();
+            assert_eq!(
+                total_mem.call(&[Val::I32(8), Val::I32(0)]).unwrap()[0].i32(),
+                Some(0)
+            );
+            let memory = exports.get_memory("memory").unwrap();
+            let read = |offset: usize, length: usize| {
+                memory.view::<u8>()[offset..offset + length]
+                    .iter()
+                    .map(|cell| cell.get())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(read(8, 8), (8u64 << 30).to_le_bytes().to_vec());
+
+            assert_eq!(
+                total_mem.call(&[Val::I32(8), Val::I32(1)]).unwrap()[0].i32(),
+                Some(101)
+            );
+            let expected = "cuDeviceTotalMem failed with CUDA error 101";
+            let last_error = exports.get_function("last_error").unwrap();
+            assert_eq!(
+                last_error.call(&[Val::I32(16), Val::I32(64)]).unwrap()[0].i32(),
+                Some(expected.len() as i32)
+            );
+            assert_eq!(read(16, expected.len()), expected.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_cuda_env_delete_null() {
+        (assert_c! {
+            #include "tests/wasmer.h"
+
+            int main() {
+                cuda_env_delete(NULL);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_env_delete_clone() {
+        (assert_c! {
+            #include "tests/wasmer.h"
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                wasm_byte_vec_t wat;
+                wasmer_byte_vec_new_from_string(&wat, "(module)");
+                wasm_byte_vec_t wasm;
+                wat2wasm(&wat, &wasm);
+                wasm_module_t* module = wasm_module_new(store, &wasm);
+                assert(module);
+
+                cuda_env_t* cuda_env = cuda_env_new();
+                assert(cuda_env);
+                assert(cuda_env_clone(NULL) == NULL);
+                cuda_env_t* clone = cuda_env_clone(cuda_env);
+                assert(clone);
+                assert(clone != cuda_env);
+
+                cuda_env_delete(cuda_env);
+
+                // the environment is still alive through the clone
+                wasm_extern_vec_t imports;
+                wasm_extern_vec_new_empty(&imports);
+                assert(cuda_get_imports(store, module, clone, &imports) == CUDA_IMPORTS_OK);
+                wasm_extern_vec_delete(&imports);
+
+                cuda_env_delete(clone);
+
+                wasm_module_delete(module);
+                wasm_byte_vec_delete(&wasm);
+                wasm_byte_vec_delete(&wat);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+
+    #[cfg(feature = "wasi")]
+    #[test]
+    fn test_cuda_env_deny_import() {
+        (assert_c! {
+            #include <string.h>
+            #include "tests/wasmer.h"
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                cuda_env_t* cuda_env = cuda_env_new();
+                assert(cuda_env);
+                assert(cuda_env_deny_import(cuda_env, "cuModuleLoadData"));
+
+                wasmer_named_extern_vec_t imports;
+                assert(cuda_get_unordered_imports(store, cuda_env, &imports));
+                assert(imports.size > 0);
+
+                for (size_t i = 0; i < imports.size; ++i) {
+                    const wasm_name_t* name = wasmer_named_extern_name(imports.data[i]);
+                    assert(!(name->size == strlen("cuModuleLoadData") &&
+                             strncmp(name->data, "cuModuleLoadData", name->size) == 0));
+                }
+
+                wasmer_named_extern_vec_delete(&imports);
+                cuda_env_delete(cuda_env);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_env_guard() {
+        (assert_cxx! {
+            #include "tests/wasmer.h"
+            #include "include/wasmer_cuda.hpp"
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                wasm_byte_vec_t wat;
+                wasmer_byte_vec_new_from_string(&wat, "(module)");
+                wasm_byte_vec_t wasm;
+                wat2wasm(&wat, &wasm);
+                wasm_module_t* module = wasm_module_new(store, &wasm);
+                assert(module);
+
+                {
+                    CudaEnvGuard cuda_env;
+                    assert(cuda_env);
+
+                    WasmExternVecGuard imports;
+                    assert(cuda_get_imports(store, module, cuda_env.get(), imports.get()) == CUDA_IMPORTS_OK);
+                    assert(imports.get()->size == 0);
+
+                    CudaEnvGuard moved = static_cast<CudaEnvGuard&&>(cuda_env);
+                    assert(moved);
+                    assert(!cuda_env);
+                }
+
+                wasm_module_delete(module);
+                wasm_byte_vec_delete(&wasm);
+                wasm_byte_vec_delete(&wat);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+
+    /// The whole lifecycle of a GPU embedder, with a mock environment
+    /// so that it runs without a GPU. `make test-capi-cuda-valgrind`
+    /// runs it under valgrind to check that nothing leaks.
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_env_guard_lifecycle() {
+        (assert_cxx! {
+            #include "tests/wasmer.h"
+            #include "include/wasmer_cuda.hpp"
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                wasm_byte_vec_t wat;
+                wasmer_byte_vec_new_from_string(
+                    &wat,
+                    "(module\n"
+                    "  (import \"nvtx\" \"nvtxRangePushA\" (func $push (param i32) (result i32)))\n"
+                    "  (import \"nvtx\" \"nvtxRangePop\" (func $pop (result i32)))\n"
+                    "  (memory (export \"memory\") 1)\n"
+                    "  (data (i32.const 16) \"kernel\\00\")\n"
+                    "  (func (export \"run\") (result i32)\n"
+                    "    (drop (call $push (i32.const 16)))\n"
+                    "    (call $pop)))"
+                );
+                wasm_byte_vec_t wasm;
+                wat2wasm(&wat, &wasm);
+                wasm_module_t* module = wasm_module_new(store, &wasm);
+                assert(module);
+
+                for (int run = 0; run < 2; ++run) {
+                    CudaEnvGuard cuda_env(cuda_env_new_mock());
+                    assert(cuda_env);
+
+                    WasmExternVecGuard imports;
+                    assert(cuda_get_imports(store, module, cuda_env.get(), imports.get()) == CUDA_IMPORTS_OK);
+                    assert(imports.get()->size == 2);
+
+                    wasm_instance_t* instance = wasm_instance_new(store, module, imports.get(), NULL);
+                    assert(instance);
+
+                    wasm_extern_vec_t exports;
+                    wasm_instance_exports(instance, &exports);
+                    wasm_func_t* run_func = wasm_extern_as_func(exports.data[1]);
+                    assert(run_func);
+
+                    wasm_val_t results_val[1] = { WASM_INIT_VAL };
+                    wasm_val_vec_t args = WASM_EMPTY_VEC;
+                    wasm_val_vec_t results = WASM_ARRAY_VEC(results_val);
+                    assert(wasm_func_call(run_func, &args, &results) == NULL);
+                    assert(cuda_env_mock_call_count(cuda_env.get(), NULL) == 0);
+
+                    wasm_extern_vec_delete(&exports);
+                    wasm_instance_delete(instance);
+                }
+
+                wasm_module_delete(module);
+                wasm_byte_vec_delete(&wasm);
+                wasm_byte_vec_delete(&wat);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_wasm_instance_get_cuda_env() {
+        (assert_c! {
+            #include "tests/wasmer.h"
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                wasm_byte_vec_t wat;
+                wasmer_byte_vec_new_from_string(&wat, "(module)");
+                wasm_byte_vec_t wasm;
+                wat2wasm(&wat, &wasm);
+                wasm_module_t* module = wasm_module_new(store, &wasm);
+                assert(module);
+
+                cuda_env_t* cuda_env = cuda_env_new();
+                assert(cuda_env);
+
+                wasm_extern_vec_t imports;
+                assert(cuda_get_imports(store, module, cuda_env, &imports) == CUDA_IMPORTS_OK);
+
+                wasm_trap_t* trap = NULL;
+                wasm_instance_t* cuda_instance = cuda_instance_new(store, module, &imports, cuda_env, &trap);
+                assert(cuda_instance);
+                assert(wasm_instance_get_cuda_env(cuda_instance) == cuda_env);
+
+                wasm_instance_t* instance = wasm_instance_new(store, module, &imports, &trap);
+                assert(instance);
+                assert(wasm_instance_get_cuda_env(instance) == NULL);
+
+                // the instance keeps the handle alive
+                cuda_env_delete(cuda_env);
+                const cuda_env_t* bound_cuda_env = wasm_instance_get_cuda_env(cuda_instance);
+                assert(bound_cuda_env == cuda_env);
+                cuda_env_t* clone = cuda_env_clone(bound_cuda_env);
+                assert(clone);
+                cuda_env_delete(clone);
+
+                wasm_instance_delete(instance);
+                wasm_instance_delete(cuda_instance);
+                wasm_extern_vec_delete(&imports);
+                wasm_module_delete(module);
+                wasm_byte_vec_delete(&wasm);
+                wasm_byte_vec_delete(&wat);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_get_imports_error_codes() {
+        (assert_c! {
+            #include <stdlib.h>
+            #include <string.h>
+            #include "tests/wasmer.h"
+
+            static void assert_last_error(const char* expected) {
+                int error_length = wasmer_last_error_length();
+                assert(error_length > 0);
+
+                char* error_message = malloc(error_length);
+                wasmer_last_error_message(error_message, error_length);
+                assert(strstr(error_message, expected) != NULL);
+                free(error_message);
+            }
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                wasm_byte_vec_t wat;
+                wasmer_byte_vec_new_from_string(
+                    &wat,
+                    "(module\n"
+                    "  (import \"host\" \"missing\" (func))\n"
+                    "  (import \"host\" \"also_missing\" (func)))"
+                );
+                wasm_byte_vec_t wasm;
+                wat2wasm(&wat, &wasm);
+                wasm_module_t* module = wasm_module_new(store, &wasm);
+                assert(module);
+
+                cuda_env_t* cuda_env = cuda_env_new();
+                assert(cuda_env);
+
+                wasm_extern_vec_t imports;
+                wasm_extern_vec_new_empty(&imports);
+
+                assert(cuda_get_imports(store, NULL, cuda_env, &imports) == CUDA_IMPORTS_NULL_ARG);
+                assert_last_error("`module` argument is NULL");
+
+                assert(cuda_get_imports(store, module, cuda_env, NULL) == CUDA_IMPORTS_NULL_ARG);
+                assert_last_error("`imports` argument is NULL");
+
+                assert(cuda_get_imports(store, module, cuda_env, &imports) == CUDA_IMPORTS_UNRESOLVED_IMPORT);
+                assert_last_error("Unresolved GPU imports:\n  host::missing\n  host::also_missing");
+
+                assert(!cuda_get_imports_bool(store, module, cuda_env, &imports));
+                assert_last_error("host::missing");
+
+                wasm_extern_vec_delete(&imports);
+                cuda_env_delete(cuda_env);
+                wasm_module_delete(module);
+                wasm_byte_vec_delete(&wasm);
+                wasm_byte_vec_delete(&wat);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_validate_module() {
+        (assert_c! {
+            #include "tests/wasmer.h"
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                wasm_byte_vec_t wat;
+                wasmer_byte_vec_new_from_string(
+                    &wat,
+                    "(module\n"
+                    "  (import \"host\" \"missing\" (func)))"
+                );
+                wasm_byte_vec_t wasm;
+                wat2wasm(&wat, &wasm);
+                wasm_module_t* module = wasm_module_new(store, &wasm);
+                assert(module);
+
+                cuda_env_t* cuda_env = cuda_env_new();
+                assert(cuda_env);
+
+                size_t resolved_imports = 42;
+                assert(cuda_validate_module(module, cuda_env, &resolved_imports) == CUDA_IMPORTS_UNRESOLVED_IMPORT);
+                assert(resolved_imports == 0);
+                assert(wasmer_last_error_length() > 0);
+                assert(cuda_validate_module(NULL, cuda_env, &resolved_imports) == CUDA_IMPORTS_NULL_ARG);
+
+                cuda_env_delete(cuda_env);
+                wasm_module_delete(module);
+                wasm_byte_vec_delete(&wasm);
+                wasm_byte_vec_delete(&wat);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+
+    #[test]
+    fn test_add_cuda_to_import_checked() {
+        use super::add_cuda_to_import_checked;
+        use wasmer_api::{imports, Exports, Function, ImportObject, NamedResolver, Store};
+        use wasmer_cuda::{add_cuda_to_import, CudaEnv};
+
+        let store = Store::default();
+
+        let mut cuda_import_object = imports! {};
+        add_cuda_to_import(&store, CudaEnv::default(), &mut cuda_import_object);
+        let (namespace, name, _) = cuda_import_object
+            .externs_vec()
+            .into_iter()
+            .find(|(_, name, _)| name == "cuMemAlloc")
+            .unwrap();
+
+        let pre_registered = || {
+            let mut exports = Exports::new();
+            exports.insert(name.clone(), Function::new_native(&store, || {}));
+            let mut import_object = ImportObject::new();
+            import_object.register(namespace.clone(), exports);
+            import_object
+        };
+
+        let mut import_object = pre_registered();
+        assert_eq!(
+            add_cuda_to_import_checked(&store, CudaEnv::default(), &mut import_object),
+            Err(vec![(namespace.clone(), name.clone())]),
+        );
+        assert_eq!(import_object.externs_vec().len(), 1);
+
+        let mut import_object = pre_registered();
+        add_cuda_to_import(&store, CudaEnv::default(), &mut import_object);
+        assert!(import_object.resolve_by_name(&namespace, &name).is_some());
+        assert_eq!(
+            import_object.externs_vec().len(),
+            cuda_import_object.externs_vec().len(),
+        );
+
+        let mut import_object = ImportObject::new();
+        assert_eq!(
+            add_cuda_to_import_checked(&store, CudaEnv::default(), &mut import_object),
+            Ok(()),
+        );
+        assert_eq!(
+            import_object.externs_vec().len(),
+            cuda_import_object.externs_vec().len(),
+        );
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_supported_functions() {
+        use super::{cuda_env_delete, cuda_env_new};
+        use wasmer_api::{imports, Instance, Module, NativeFunc, Store};
+
+        let store = Store::default();
+        let handle = cuda_env_new();
+        let cuda_env = unsafe { &*handle };
+        cuda_env
+            .shared
+            .denied_imports
+            .write()
+            .unwrap()
+            .insert("cuModuleLoadData".to_string());
+
+        let mut import_object = imports! {};
+        cuda_env.add_to_import(&store, &mut import_object);
+        let (namespace, _, _) = import_object
+            .externs_vec()
+            .into_iter()
+            .find(|(_, name, _)| name == "cuMemAlloc")
+            .unwrap();
+        let supported_functions = |namespace: &str| {
+            let module = Module::new(
+                &store,
+                format!(
+                    r#"(module
+                      (import "{}" "cuda_supported_functions" (func $supported (param i32 i32) (result i32)))
+                      (memory (export "memory") 1)
+                      (func (export "supported") (param i32 i32) (result i32)
+                        (call $supported (local.get 0) (local.get 1))))"#,
+                    namespace,
+                ),
+            )
+            .unwrap();
+            let instance = Instance::new(&module, &import_object).unwrap();
+            let supported: NativeFunc<(i32, i32), i32> =
+                instance.exports.get_native_function("supported").unwrap();
+
+            let length = supported.call(0, 0).unwrap();
+            assert!(length > 0);
+            assert_eq!(supported.call(16, length).unwrap(), length);
+
+            let memory = instance.exports.get_memory("memory").unwrap();
+            let names = memory.view::<u8>()[16..16 + length as usize]
+                .iter()
+                .map(|cell| cell.get())
+                .collect::<Vec<_>>();
+
+            String::from_utf8(names).unwrap()
+        };
+
+        // the list is exactly what is registered in the namespace
+        let registered = |namespace: &str| {
+            let mut names = import_object
+                .externs_vec()
+                .into_iter()
+                .filter(|(ns, _, _)| ns == namespace)
+                .map(|(_, name, _)| name)
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let listed = |names: &str| {
+            let mut names = names.lines().map(str::to_string).collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        let names = supported_functions(&namespace);
+        assert_eq!(listed(&names), registered(&namespace));
+        assert!(names.lines().any(|name| name == "cuMemAlloc"));
+        assert!(!names.lines().any(|name| name == "cuModuleLoadData"));
+        // along with the helpers of the C API, itself included
+        for helper in &[
+            "cuGetLastErrorString",
+            "cuda_error_name",
+            "cuda_error_string",
+            "cuda_supported_functions",
+        ] {
+            assert!(names.lines().any(|name| name == *helper));
+        }
+
+        // the namespaces added by the C API have their own list
+        let names = supported_functions("nvtx");
+        assert_eq!(listed(&names), registered("nvtx"));
+        assert!(names.lines().any(|name| name == "nvtxRangePushA"));
+
+        unsafe { cuda_env_delete(handle) };
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_env_last_error() {
+        use super::mock::cuda_env_new_mock;
+        use super::{
+            cuda_env_delete, cuda_env_last_error, cuda_namespaces, fallback_stub, merge_imports,
+        };
+        use crate::wasm_c_api::types::wasm_byte_vec_t;
+        use wasmer_api::{
+            imports, Extern, FunctionType, Instance, Module, NativeFunc, Store, Type,
+        };
+
+        let store = Store::default();
+        let namespace = cuda_namespaces(&store).into_iter().next().unwrap();
+        let handle_a = cuda_env_new_mock();
+        let handle_b = cuda_env_new_mock();
+        let (cuda_env_a, cuda_env_b) = unsafe { (&*handle_a, &*handle_b) };
+
+        // env A: a stubbed call fails
+        let mut import_object = imports! {};
+        cuda_env_a.add_to_import(&store, &mut import_object);
+        let stub = fallback_stub(
+            &store,
+            FunctionType::new(vec![Type::I32], vec![Type::I32]),
+            "host::cudaMissing".to_string(),
+            cuda_env_a.shared.clone(),
+        );
+        merge_imports(
+            &mut import_object,
+            vec![(
+                "host".to_string(),
+                "cudaMissing".to_string(),
+                Extern::from(stub),
+            )],
+        );
+        let module = Module::new(
+            &store,
+            format!(
+                r#"(module
+                  (import "host" "cudaMissing" (func $missing (param i32) (result i32)))
+                  (import "{0}" "cuGetLastErrorString" (func $last_error (param i32 i32) (result i32)))
+                  (import "{0}" "cuMemGetInfo" (func $mem_info (param i32 i32) (result i32)))
+                  (memory (export "memory") 1)
+                  (func (export "missing") (result i32)
+                    (call $missing (i32.const 7)))
+                  (func (export "last_error") (param i32 i32) (result i32)
+                    (call $last_error (local.get 0) (local.get 1)))
+                  (func (export "mem_info") (param i32 i32) (result i32)
+                    (call $mem_info (local.get 0) (local.get 1))))"#,
+                namespace,
+            ),
+        )
+        .unwrap();
+        let instance_a = Instance::new(&module, &import_object).unwrap();
+        let missing: NativeFunc<(), i32> =
+            instance_a.exports.get_native_function("missing").unwrap();
+        let last_error: NativeFunc<(i32, i32), i32> = instance_a
+            .exports
+            .get_native_function("last_error")
+            .unwrap();
+        assert_eq!(last_error.call(0, 0).unwrap(), 0);
+        assert_eq!(missing.call().unwrap(), 801);
+
+        // env B: a call succeeds
+        let mut import_object = imports! {};
+        cuda_env_b.add_to_import(&store, &mut import_object);
+        let module = Module::new(
+            &store,
+            format!(
+                r#"(module
+                  (import "{}" "cuda_supported_functions" (func $supported (param i32 i32) (result i32)))
+                  (memory (export "memory") 1)
+                  (func (export "supported") (result i32)
+                    (call $supported (i32.const 0) (i32.const 0))))"#,
+                namespace,
+            ),
+        )
+        .unwrap();
+        let instance_b = Instance::new(&module, &import_object).unwrap();
+        let supported: NativeFunc<(), i32> =
+            instance_b.exports.get_native_function("supported").unwrap();
+        assert!(supported.call().unwrap() > 0);
+
+        let expected = "host::cudaMissing failed with CUDA error 801";
+        let mut message: wasm_byte_vec_t = Vec::new().into();
+        assert!(cuda_env_last_error(Some(cuda_env_a), &mut message));
+        assert_eq!(message.as_slice(), expected.as_bytes());
+        drop(message);
+        let mut message: wasm_byte_vec_t = Vec::new().into();
+        assert!(!cuda_env_last_error(Some(cuda_env_b), &mut message));
+        assert!(message.as_slice().is_empty());
+        drop(message);
+
+        // the guest reads the same message, truncated to its buffer
+        let length = expected.len() as i32;
+        assert_eq!(last_error.call(0, 0).unwrap(), length);
+        assert_eq!(last_error.call(16, 4).unwrap(), length);
+        assert_eq!(last_error.call(32, length).unwrap(), length);
+        let memory = instance_a.exports.get_memory("memory").unwrap();
+        let read = |offset: usize, length: usize| {
+            memory.view::<u8>()[offset..offset + length]
+                .iter()
+                .map(|cell| cell.get())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(read(16, 4), b"host");
+        assert_eq!(read(32, expected.len()), expected.as_bytes());
+        assert_eq!(last_error.call(65536, length).unwrap(), -1);
+
+        // the device queries record their failures too, e.g. an out of
+        // bounds pointer
+        let mem_info: NativeFunc<(i32, i32), i32> =
+            instance_a.exports.get_native_function("mem_info").unwrap();
+        assert_eq!(mem_info.call(8, 65536).unwrap(), 1);
+        let mut message: wasm_byte_vec_t = Vec::new().into();
+        assert!(cuda_env_last_error(Some(cuda_env_a), &mut message));
+        assert_eq!(message.as_slice(), b"cuMemGetInfo failed with CUDA error 1");
+        drop(message);
+
+        unsafe {
+            cuda_env_delete(handle_a);
+            cuda_env_delete(handle_b);
+        }
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_error_strings() {
+        use super::{cuda_env_delete, cuda_env_new, cuda_namespaces};
+        use wasmer_api::{imports, Instance, Module, NativeFunc, Store};
+
+        let store = Store::default();
+        let namespace = cuda_namespaces(&store).into_iter().next().unwrap();
+        let handle = cuda_env_new();
+        let cuda_env = unsafe { &*handle };
+
+        let mut import_object = imports! {};
+        cuda_env.add_to_import(&store, &mut import_object);
+        let module = Module::new(
+            &store,
+            format!(
+                r#"(module
+                  (import "{0}" "cuda_error_name" (func $name (param i32 i32 i32) (result i32)))
+                  (import "{0}" "cuda_error_string" (func $string (param i32 i32 i32) (result i32)))
+                  (memory (export "memory") 1)
+                  (func (export "name") (param i32 i32 i32) (result i32)
+                    (call $name (local.get 0) (local.get 1) (local.get 2)))
+                  (func (export "string") (param i32 i32 i32) (result i32)
+                    (call $string (local.get 0) (local.get 1) (local.get 2))))"#,
+                namespace,
+            ),
+        )
+        .unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let name: NativeFunc<(i32, i32, i32), i32> =
+            instance.exports.get_native_function("name").unwrap();
+        let string: NativeFunc<(i32, i32, i32), i32> =
+            instance.exports.get_native_function("string").unwrap();
+        let memory = instance.exports.get_memory("memory").unwrap();
+        let read = |length: i32| {
+            let bytes = memory.view::<u8>()[..length as usize]
+                .iter()
+                .map(|cell| cell.get())
+                .collect::<Vec<_>>();
+
+            String::from_utf8(bytes).unwrap()
+        };
+
+        let length = name.call(2, 0, 64).unwrap();
+        assert_eq!(read(length), "CUDA_ERROR_OUT_OF_MEMORY");
+        let length = string.call(2, 0, 64).unwrap();
+        assert_eq!(read(length), "out of memory");
+
+        // unknown codes don't fail
+        let length = name.call(123_456, 0, 64).unwrap();
+        assert_eq!(read(length), "unknown error");
+        let length = string.call(123_456, 0, 64).unwrap();
+        assert_eq!(read(length), "unknown error");
+
+        // the guest buffer is bounds checked, and the whole length returned
+        assert_eq!(name.call(2, 0, 4).unwrap(), 24);
+        assert_eq!(name.call(2, 65536, 64).unwrap(), -1);
+
+        unsafe { cuda_env_delete(handle) };
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_env_set_capabilities() {
+        use super::capabilities::{capability_of, cuda_capability_t, cuda_env_set_capabilities};
+        use super::mock::{cuda_env_mock_call_count, cuda_env_new_mock};
+        use super::{cuda_env_delete, cuda_env_last_error};
+        use crate::wasm_c_api::types::wasm_byte_vec_t;
+        use std::ffi::CString;
+        use wasmer_api::{imports, Extern, Function, Instance, Module, Store};
+
+        assert_eq!(
+            capability_of("cuMemcpyHtoD"),
+            cuda_capability_t::CUDA_CAPABILITY_TRANSFER
+        );
+        assert_eq!(
+            capability_of("cuMemAlloc"),
+            cuda_capability_t::CUDA_CAPABILITY_MEMORY
+        );
+        assert_eq!(
+            capability_of("cuLaunchKernel"),
+            cuda_capability_t::CUDA_CAPABILITY_LAUNCH
+        );
+        assert_eq!(
+            capability_of("cuInit"),
+            cuda_capability_t::CUDA_CAPABILITY_DEVICE_QUERY
+        );
+
+        // a mock environment, so that the allowed calls don't reach a GPU
+        let store = Store::default();
+        let handle = cuda_env_new_mock();
+        let cuda_env = unsafe { &*handle };
+        cuda_env_set_capabilities(cuda_env, cuda_capability_t::CUDA_CAPABILITY_TRANSFER as u32);
+
+        let mut import_object = imports! {};
+        cuda_env.add_to_import(&store, &mut import_object);
+        let externs = import_object.externs_vec();
+        let find = |capability| {
+            externs
+                .iter()
+                .find_map(|(namespace, name, extern_)| match extern_ {
+                    Extern::Function(function) if capability_of(name) == capability => {
+                        Some((namespace.clone(), name.clone(), function.ty().clone()))
+                    }
+                    _ => None,
+                })
+                .unwrap()
+        };
+        let launch = find(cuda_capability_t::CUDA_CAPABILITY_LAUNCH);
+        let copy = find(cuda_capability_t::CUDA_CAPABILITY_TRANSFER);
+
+        let mut wat = String::from("(module");
+        for (export, (namespace, name, ty)) in [("launch", &launch), ("copy", &copy)].iter() {
+            wat += &format!(
+                r#"(import "{}" "{}" (func ${} (param{}) (result{})))
+                (export "{}" (func ${}))"#,
+                namespace,
+                name,
+                export,
+                wat_types(ty.params()),
+                wat_types(ty.results()),
+                export,
+                export,
+            );
+        }
+        wat += r#"(memory (export "memory") 1))"#;
+        let module = Module::new(&store, wat).unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let call = |export: &str| {
+            let function: &Function = instance.exports.get_function(export).unwrap();
+
+            function
+                .call(&args_of(function.ty().params(), 0))
+                .unwrap()
+                .first()
+                .and_then(|result| result.i32())
+        };
+        let last_error = || {
+            let mut message: wasm_byte_vec_t = Vec::new().into();
+            cuda_env_last_error(Some(cuda_env), &mut message);
+
+            String::from_utf8(message.as_slice().to_vec()).unwrap()
+        };
+        let call_count = |name: &str| {
+            let name = CString::new(name).unwrap();
+
+            unsafe { cuda_env_mock_call_count(cuda_env, name.as_ptr()) }
+        };
+
+        // launches are rejected before reaching the cuda imports
+        assert_eq!(call("launch"), Some(800));
+        assert_eq!(call_count(&launch.1), 0);
+        let expected = format!("{}::{} failed with CUDA error 800", launch.0, launch.1);
+        assert_eq!(last_error(), expected);
+
+        // copies reach them
+        assert_ne!(call("copy"), Some(800));
+        assert_eq!(call_count(&copy.1), 1);
+        assert_eq!(last_error(), expected);
+
+        unsafe { cuda_env_delete(handle) };
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_get_imports_with_fallbacks() {
+        (assert_c! {
+            #include "tests/wasmer.h"
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                wasm_byte_vec_t wat;
+                wasmer_byte_vec_new_from_string(
+                    &wat,
+                    "(module\n"
+                    "  (import \"host\" \"cudaMissing\" (func $missing (param i32) (result i32)))\n"
+                    "  (func (export \"call_missing\") (result i32)\n"
+                    "    (call $missing (i32.const 7))))"
+                );
+                wasm_byte_vec_t wasm;
+                wat2wasm(&wat, &wasm);
+                wasm_module_t* module = wasm_module_new(store, &wasm);
+                assert(module);
+
+                cuda_env_t* cuda_env = cuda_env_new();
+                assert(cuda_env);
+
+                wasm_extern_vec_t imports;
+                wasm_extern_vec_new_empty(&imports);
+                assert(cuda_get_imports(store, module, cuda_env, &imports) == CUDA_IMPORTS_UNRESOLVED_IMPORT);
+                assert(cuda_get_imports_with_fallbacks(store, module, cuda_env, &imports));
+                assert(imports.size == 1);
+
+                wasm_trap_t* trap = NULL;
+                wasm_instance_t* instance = wasm_instance_new(store, module, &imports, &trap);
+                assert(instance);
+
+                wasm_extern_vec_t exports;
+                wasm_instance_exports(instance, &exports);
+                assert(exports.size == 1);
+                const wasm_func_t* call_missing = wasm_extern_as_func(exports.data[0]);
+
+                wasm_val_t results_val[1] = { WASM_INIT_VAL };
+                wasm_val_vec_t args = WASM_EMPTY_VEC;
+                wasm_val_vec_t results = WASM_ARRAY_VEC(results_val);
+                assert(wasm_func_call(call_missing, &args, &results) == NULL);
+                assert(results_val[0].of.i32 == 801);
+
+                wasm_extern_vec_delete(&exports);
+                wasm_instance_delete(instance);
+                wasm_extern_vec_delete(&imports);
+                cuda_env_delete(cuda_env);
+                wasm_module_delete(module);
+                wasm_byte_vec_delete(&wasm);
+                wasm_byte_vec_delete(&wat);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_env_import_names() {
+        use super::{cuda_env_delete, cuda_env_new, cuda_import_types};
+        use std::collections::{HashMap, HashSet};
+        use wasmer_api::{imports, Store};
+
+        let store = Store::default();
+        let handle = cuda_env_new();
+        let cuda_env = unsafe { &*handle };
+
+        // `cuda_validate_module` must agree with the imports actually built
+        let registered = || {
+            let mut import_object = imports! {};
+            cuda_env.add_to_import(&store, &mut import_object);
+
+            import_object
+                .externs_vec()
+                .into_iter()
+                .map(|(namespace, name, _)| (namespace, name))
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(cuda_env.import_names(&store), registered());
+
+        cuda_env
+            .shared
+            .denied_imports
+            .write()
+            .unwrap()
+            .insert("cuMemAlloc".to_string());
+        assert_eq!(cuda_env.import_names(&store), registered());
+
+        // the helpers of the C API can be denied like the cuda imports
+        cuda_env
+            .shared
+            .denied_imports
+            .write()
+            .unwrap()
+            .insert("cuda_error_name".to_string());
+        let names = registered();
+        assert!(!names.iter().any(|(_, name)| name == "cuda_error_name"));
+        assert!(names.iter().any(|(_, name)| name == "cuda_error_string"));
+        assert_eq!(cuda_env.import_names(&store), names);
+
+        // and only the allowed ones are registered
+        let (_, allowed, _) = cuda_import_types(&store)
+            .iter()
+            .find(|(_, name, _)| name != "cuMemAlloc")
+            .cloned()
+            .unwrap();
+        cuda_env
+            .shared
+            .allowed_imports
+            .write()
+            .unwrap()
+            .insert(allowed.clone());
+        let names = registered();
+        assert!(names.iter().all(|(_, name)| *name == allowed));
+        assert!(!names.is_empty());
+        assert_eq!(cuda_env.import_names(&store), names);
+
+        unsafe { cuda_env_delete(handle) };
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_import_types_cached() {
+        use super::cuda_import_types;
+        use std::sync::Arc;
+        use wasmer_api::Store;
+
+        let import_types = cuda_import_types(&Store::default());
+        assert!(!import_types.is_empty());
+        assert!(Arc::ptr_eq(
+            &import_types,
+            &cuda_import_types(&Store::default())
+        ));
+    }
+
+    #[cfg(all(feature = "wat", feature = "universal", feature = "cranelift"))]
+    #[test]
+    fn test_wasm_config_cuda_require_compiler() {
+        use super::{cuda_namespaces, wasm_config_cuda_require_compiler, wasmer_module_uses_cuda};
+        use crate::wasm_c_api::engine::{
+            wasm_config_new, wasm_config_set_compiler, wasm_engine_new_with_config,
+            wasmer_compiler_t,
+        };
+        use crate::wasm_c_api::module::wasm_module_new;
+        use crate::wasm_c_api::store::wasm_store_new;
+        use crate::wasm_c_api::types::wasm_byte_vec_t;
+        use wasmer_api::wat2wasm;
+
+        unsafe {
+            let mut config = wasm_config_new();
+            wasm_config_set_compiler(&mut config, wasmer_compiler_t::CRANELIFT);
+            assert!(!wasm_config_cuda_require_compiler(&mut config, b"unknown\0".as_ptr() as _));
+            assert!(wasm_config_cuda_require_compiler(&mut config, b"LLVM\0".as_ptr() as _));
+
+            let engine = wasm_engine_new_with_config(Some(config)).unwrap();
+            let store = wasm_store_new(Some(&engine)).unwrap();
+            let namespace = cuda_namespaces(&store.inner).into_iter().next().unwrap();
+
+            let cuda_wasm: wasm_byte_vec_t = wat2wasm(
+                format!(r#"(module (import "{}" "cuda_function" (func)))"#, namespace).as_bytes(),
+            )
+            .unwrap()
+            .into_owned()
+            .into();
+            assert!(wasm_module_new(Some(&store), Some(&cuda_wasm)).is_none());
+
+            let wasm: wasm_byte_vec_t = wat2wasm(b"(module)").unwrap().into_owned().into();
+            let module = wasm_module_new(Some(&store), Some(&wasm)).unwrap();
+            assert!(!wasmer_module_uses_cuda(Some(&module)));
+        }
+    }
+
+    /// Regression test for host calls from a module using the cuda
+    /// imports: the arguments of a cuda import taking an `i64` must
+    /// reach the host unchanged, whatever the compiler.
+    ///
+    /// `cuMemcpyHtoD(dst, src, len)` is replaced by a native host
+    /// function with the same name and signature, made like the ones of
+    /// `wasmer-cuda`, so that the call goes through the same trampolines.
+    /// It records its arguments instead of calling CUDA, so that no
+    /// device is needed.
+    #[cfg(all(feature = "wat", feature = "universal", feature = "compiler"))]
+    fn check_i64_host_call(compiler_config: Box<dyn wasmer_api::CompilerConfig>) {
+        use super::{cuda_import_types, merge_imports};
+        use std::sync::{Arc, Mutex};
+        use wasmer_api::{
+            imports, Extern, Function, FunctionType, Instance, Module, NativeFunc, Store, Type,
+            WasmerEnv,
+        };
+        use wasmer_engine_universal::Universal;
+
+        const NAME: &str = "cuMemcpyHtoD";
+        const DST: i64 = -0x0123_4567_89ab_cdef;
+        const SRC: i32 = 0x0765_4321;
+        const LEN: i32 = 0x0123_4567;
+
+        #[derive(Clone, Default)]
+        struct Received {
+            args: Arc<Mutex<Option<(i64, i32, i32)>>>,
+        }
+
+        impl WasmerEnv for Received {}
+
+        fn cu_memcpy_htod(env: &Received, dst: i64, src: i32, len: i32) -> i32 {
+            *env.args.lock().unwrap() = Some((dst, src, len));
+
+            0
+        }
+
+        let store = Store::new(&Universal::new(compiler_config).engine());
+        let (namespace, _, ty) = cuda_import_types(&store)
+            .iter()
+            .find(|(_, name, _)| name == NAME)
+            .cloned()
+            .expect("no cuMemcpyHtoD cuda import");
+        assert_eq!(
+            ty.func(),
+            Some(&FunctionType::new(
+                vec![Type::I64, Type::I32, Type::I32],
+                vec![Type::I32]
+            )),
+            "the signature of {} has changed, update the host function of this test",
+            NAME,
+        );
+
+        let received = Received::default();
+        let function = Function::new_native_with_env(&store, received.clone(), cu_memcpy_htod);
+        let mut import_object = imports! {};
+        merge_imports(
+            &mut import_object,
+            vec![(namespace.clone(), NAME.to_string(), Extern::from(function))],
+        );
+
+        let module = Module::new(
+            &store,
+            format!(
+                r#"(module
+                  (import "{}" "{}" (func $cuda (param i64 i32 i32) (result i32)))
+                  (func (export "run") (result i32)
+                    (call $cuda (i64.const {}) (i32.const {}) (i32.const {}))))"#,
+                namespace, NAME, DST, SRC, LEN,
+            ),
+        )
+        .unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let run: NativeFunc<(), i32> = instance.exports.get_native_function("run").unwrap();
+        assert_eq!(run.call().unwrap(), 0);
+
+        assert_eq!(*received.args.lock().unwrap(), Some((DST, SRC, LEN)));
+    }
+
+    #[cfg(all(feature = "wat", feature = "universal", feature = "cranelift"))]
+    #[test]
+    fn test_i64_host_call_cranelift() {
+        check_i64_host_call(Box::new(wasmer_compiler_cranelift::Cranelift::default()));
+    }
+
+    #[cfg(all(feature = "wat", feature = "universal", feature = "llvm"))]
+    #[test]
+    fn test_i64_host_call_llvm() {
+        check_i64_host_call(Box::new(wasmer_compiler_llvm::LLVM::default()));
+    }
+
+    /// Cranelift and LLVM pass the arguments unchanged. Singlepass
+    /// doesn't on some targets, which is what this test captures, so it
+    /// is ignored until that is fixed. Meanwhile, embedders can refuse
+    /// singlepass with `wasm_config_cuda_require_compiler`.
+    #[cfg(all(feature = "wat", feature = "universal", feature = "singlepass"))]
+    #[test]
+    #[ignore = "singlepass passes wrong argument values to the cuda host functions on some targets"]
+    fn test_i64_host_call_singlepass() {
+        check_i64_host_call(Box::new(wasmer_compiler_singlepass::Singlepass::default()));
+    }
+}
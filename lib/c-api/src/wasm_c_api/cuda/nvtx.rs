@@ -0,0 +1,295 @@
+//! NVTX range markers, so that Nsight Systems can show named ranges
+//! on the timeline of a Wasm GPU workload.
+//!
+//! The guest imports are always available. With the `nvtx` feature,
+//! `libnvToolsExt` is loaded at runtime with `libloading`. Without it,
+//! or if it cannot be loaded, every marker is a no-op that returns
+//! `NVTX_NO_PUSH_POP_TRACKING`, like NVTX does when no tool is
+//! attached, so that a module using NVTX runs the same under any
+//! build.
+//!
+//! The C functions, `cuda_nvtx_range_push` and `cuda_nvtx_range_pop`,
+//! are only built with the `nvtx` feature, i.e. when
+//! `WASMER_NVTX_ENABLED` is defined.
+
+#[cfg(feature = "nvtx")]
+use lazy_static::lazy_static;
+#[cfg(feature = "nvtx")]
+use libloading::Library;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use wasmer_api::{
+    Array, Exports, Function, HostEnvInitError, ImportObject, Instance, LazyInit, Memory, Store,
+    WasmPtr, WasmerEnv,
+};
+
+/// The namespace of the guest imports.
+pub(super) const NVTX_NAMESPACE: &str = "nvtx";
+
+/// The names of the guest imports, see `add_nvtx_to_import`.
+pub(super) const NVTX_IMPORTS: &[&str] = &["nvtxRangePushA", "nvtxRangePush", "nvtxRangePop"];
+
+/// Returned when the NVTX library isn't available.
+const NVTX_NO_PUSH_POP_TRACKING: c_int = -2;
+
+#[cfg(feature = "nvtx")]
+type NvtxRangePushA = unsafe extern "C" fn(message: *const c_char) -> c_int;
+#[cfg(feature = "nvtx")]
+type NvtxRangePop = unsafe extern "C" fn() -> c_int;
+
+#[cfg(feature = "nvtx")]
+struct Nvtx {
+    range_push: NvtxRangePushA,
+    range_pop: NvtxRangePop,
+    // keep the library loaded as long as the function pointers live
+    _library: Library,
+}
+
+#[cfg(feature = "nvtx")]
+impl Nvtx {
+    unsafe fn load() -> Option<Self> {
+        let library = Library::new(libloading::library_filename("nvToolsExt")).ok()?;
+        let range_push = *library.get::<NvtxRangePushA>(b"nvtxRangePushA\0").ok()?;
+        let range_pop = *library.get::<NvtxRangePop>(b"nvtxRangePop\0").ok()?;
+
+        Some(Self {
+            range_push,
+            range_pop,
+            _library: library,
+        })
+    }
+}
+
+#[cfg(feature = "nvtx")]
+lazy_static! {
+    static ref NVTX: Option<Nvtx> = unsafe { Nvtx::load() };
+}
+
+#[cfg(feature = "nvtx")]
+fn range_push(message: &CStr) -> c_int {
+    match NVTX.as_ref() {
+        Some(nvtx) => unsafe { (nvtx.range_push)(message.as_ptr()) },
+        None => NVTX_NO_PUSH_POP_TRACKING,
+    }
+}
+
+#[cfg(feature = "nvtx")]
+fn range_pop() -> c_int {
+    match NVTX.as_ref() {
+        Some(nvtx) => unsafe { (nvtx.range_pop)() },
+        None => NVTX_NO_PUSH_POP_TRACKING,
+    }
+}
+
+#[cfg(not(feature = "nvtx"))]
+fn range_push(_message: &CStr) -> c_int {
+    NVTX_NO_PUSH_POP_TRACKING
+}
+
+#[cfg(not(feature = "nvtx"))]
+fn range_pop() -> c_int {
+    NVTX_NO_PUSH_POP_TRACKING
+}
+
+/// Start a nested NVTX range named `label`.
+///
+/// Returns the zero-based depth of the started range, or a negative
+/// value if an error occurred or if `libnvToolsExt` isn't available.
+#[cfg(feature = "nvtx")]
+#[no_mangle]
+pub unsafe extern "C" fn cuda_nvtx_range_push(label: *const c_char) -> i32 {
+    debug_assert!(!label.is_null());
+
+    range_push(CStr::from_ptr(label))
+}
+
+/// End the innermost NVTX range started by `cuda_nvtx_range_push`.
+///
+/// Returns the zero-based depth of the ended range, or a negative
+/// value if an error occurred or if `libnvToolsExt` isn't available.
+#[cfg(feature = "nvtx")]
+#[no_mangle]
+pub extern "C" fn cuda_nvtx_range_pop() -> i32 {
+    range_pop()
+}
+
+#[derive(Clone, Default)]
+struct NvtxEnv {
+    memory: LazyInit<Memory>,
+}
+
+impl WasmerEnv for NvtxEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        let memory: Memory = instance.exports.get_with_generics_weak("memory")?;
+        self.memory.initialize(memory);
+        Ok(())
+    }
+}
+
+fn nvtx_range_push_a(env: &NvtxEnv, message: WasmPtr<u8, Array>) -> i32 {
+    let name = env
+        .memory
+        .get_ref()
+        .and_then(|memory| message.get_utf8_string_with_nul(memory))
+        .and_then(|name| CString::new(name).ok());
+
+    match name {
+        Some(name) => {
+            log::debug!("nvtxRangePushA({:?})", name);
+            range_push(&name)
+        }
+        None => {
+            log::warn!(
+                "nvtxRangePushA: invalid range name at offset {}",
+                message.offset()
+            );
+            -1
+        }
+    }
+}
+
+fn nvtx_range_pop() -> i32 {
+    range_pop()
+}
+
+/// add the `nvtx` namespace to `import_object`, so that guests can
+/// push and pop NVTX ranges themselves
+///
+/// The range name is a nul-terminated string in the guest memory.
+pub(super) fn add_nvtx_to_import(store: &Store, import_object: &mut ImportObject) {
+    let mut namespace = Exports::new();
+    namespace.insert(
+        "nvtxRangePushA",
+        Function::new_native_with_env(store, NvtxEnv::default(), nvtx_range_push_a),
+    );
+    // same as `nvtxRangePushA`, for guests that don't use the NVTX header names
+    namespace.insert(
+        "nvtxRangePush",
+        Function::new_native_with_env(store, NvtxEnv::default(), nvtx_range_push_a),
+    );
+    namespace.insert("nvtxRangePop", Function::new_native(store, nvtx_range_pop));
+
+    import_object.register(NVTX_NAMESPACE, namespace);
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_nvtx_imports_in_any_build() {
+        use super::super::cuda_env_delete;
+        use super::super::mock::cuda_env_new_mock;
+        use super::NVTX_NO_PUSH_POP_TRACKING;
+        use wasmer_api::{imports, Instance, Module, NativeFunc, Store};
+
+        let store = Store::default();
+        let handle = cuda_env_new_mock();
+        let cuda_env = unsafe { &*handle };
+
+        // a module using NVTX instantiates under any build
+        let mut import_object = imports! {};
+        cuda_env.add_to_import(&store, &mut import_object);
+        let module = Module::new(
+            &store,
+            r#"(module
+              (import "nvtx" "nvtxRangePushA" (func $push (param i32) (result i32)))
+              (import "nvtx" "nvtxRangePop" (func $pop (result i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 16) "kernel\00")
+              (func (export "push") (result i32)
+                (call $push (i32.const 16)))
+              (func (export "pop") (result i32)
+                (call $pop)))"#,
+        )
+        .unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let push: NativeFunc<(), i32> = instance.exports.get_native_function("push").unwrap();
+        let pop: NativeFunc<(), i32> = instance.exports.get_native_function("pop").unwrap();
+
+        let pushed = push.call().unwrap();
+        let popped = pop.call().unwrap();
+        if cfg!(feature = "nvtx") {
+            assert!(pushed >= 0 || pushed == NVTX_NO_PUSH_POP_TRACKING);
+            assert!(popped >= 0 || popped == NVTX_NO_PUSH_POP_TRACKING);
+        } else {
+            assert_eq!(pushed, NVTX_NO_PUSH_POP_TRACKING);
+            assert_eq!(popped, NVTX_NO_PUSH_POP_TRACKING);
+        }
+
+        // and calls no cuda import
+        assert_eq!(cuda_env.mock_calls().unwrap().len(), 0);
+
+        unsafe { cuda_env_delete(handle) };
+    }
+
+    /// whether `libnvToolsExt` has been loaded
+    #[cfg(any(feature = "nvtx", feature = "wat"))]
+    fn nvtx_loaded() -> bool {
+        #[cfg(feature = "nvtx")]
+        return super::NVTX.is_some();
+        #[cfg(not(feature = "nvtx"))]
+        return false;
+    }
+
+    #[cfg(feature = "nvtx")]
+    #[test]
+    fn test_cuda_nvtx_range_without_library() {
+        use super::{cuda_nvtx_range_pop, cuda_nvtx_range_push, NVTX_NO_PUSH_POP_TRACKING};
+
+        if nvtx_loaded() {
+            return;
+        }
+
+        unsafe {
+            assert_eq!(
+                cuda_nvtx_range_push(b"kernel\0".as_ptr() as _),
+                NVTX_NO_PUSH_POP_TRACKING
+            );
+        }
+        assert_eq!(cuda_nvtx_range_pop(), NVTX_NO_PUSH_POP_TRACKING);
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_nvtx_range_name_from_guest_memory() {
+        use super::add_nvtx_to_import;
+        use super::NVTX_NO_PUSH_POP_TRACKING;
+        use wasmer_api::{imports, Instance, Module, NativeFunc, Store};
+
+        let store = Store::default();
+        let mut import_object = imports! {};
+        add_nvtx_to_import(&store, &mut import_object);
+        let module = Module::new(
+            &store,
+            r#"(module
+              (import "nvtx" "nvtxRangePushA" (func $push_a (param i32) (result i32)))
+              (import "nvtx" "nvtxRangePush" (func $push (param i32) (result i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 16) "kernel\00")
+              (data (i32.const 32) "\ff\fe\00")
+              (func (export "push_a") (param i32) (result i32)
+                (call $push_a (local.get 0)))
+              (func (export "push") (param i32) (result i32)
+                (call $push (local.get 0))))"#,
+        )
+        .unwrap();
+        let instance = Instance::new(&module, &import_object).unwrap();
+        let push_a: NativeFunc<i32, i32> = instance.exports.get_native_function("push_a").unwrap();
+        let push: NativeFunc<i32, i32> = instance.exports.get_native_function("push").unwrap();
+
+        for push in &[push_a, push] {
+            // a valid name goes to NVTX, or to the no-op without it
+            let pushed = push.call(16).unwrap();
+            if nvtx_loaded() {
+                // NVTX itself returns it when no tool is attached
+                assert!(pushed >= 0 || pushed == NVTX_NO_PUSH_POP_TRACKING);
+            } else {
+                assert_eq!(pushed, NVTX_NO_PUSH_POP_TRACKING);
+            }
+
+            // invalid names never reach NVTX
+            assert_eq!(push.call(32).unwrap(), -1);
+            assert_eq!(push.call(0x10000).unwrap(), -1);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! `cuda_supported_functions`, so that a guest can find out at
+//! runtime which cuda imports the host provides, and adapt to
+//! different versions of the import table.
+//!
+//! It is registered in every namespace of the imports of a
+//! `cuda_env_t`, unless the namespace already provides it, and lists
+//! the imports registered in that namespace, i.e. the cuda imports and
+//! the ones added by the C API, e.g. the NVTX markers, after the allow
+//! list and the deny list are applied, itself included.
+
+use std::sync::Arc;
+use wasmer_api::{
+    Array, Extern, Function, HostEnvInitError, Instance, LazyInit, Memory, Store, WasmPtr,
+    WasmerEnv,
+};
+
+pub(super) const SUPPORTED_FUNCTIONS_NAME: &str = "cuda_supported_functions";
+
+#[derive(Clone)]
+struct SupportedFunctionsEnv {
+    /// newline-separated import names
+    names: Arc<Vec<u8>>,
+    memory: LazyInit<Memory>,
+}
+
+impl WasmerEnv for SupportedFunctionsEnv {
+    fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+        let memory: Memory = instance.exports.get_with_generics_weak("memory")?;
+        self.memory.initialize(memory);
+        Ok(())
+    }
+}
+
+/// Copy as much of the list as fits in the `len` bytes at `buf`, and
+/// return the length of the whole list, so that a guest can call it
+/// with `len == 0` first to size its buffer.
+///
+/// The list isn't nul-terminated. Returns -1 if `buf` is out of
+/// bounds.
+fn cuda_supported_functions(env: &SupportedFunctionsEnv, buf: WasmPtr<u8, Array>, len: i32) -> i32 {
+    write_to_guest(&env.memory, SUPPORTED_FUNCTIONS_NAME, buf, len, &env.names)
+}
+
+/// Copy as much of `bytes` as fits in the `len` bytes at `buf`, and
+/// return the length of `bytes`, or -1 if `buf` is out of bounds.
+/// `import` names the calling import in the logs.
+pub(super) fn write_to_guest(
+    memory: &LazyInit<Memory>,
+    import: &str,
+    buf: WasmPtr<u8, Array>,
+    len: i32,
+    bytes: &[u8],
+) -> i32 {
+    let count = (len.max(0) as usize).min(bytes.len()) as u32;
+
+    if count > 0 {
+        let cells = memory
+            .get_ref()
+            .and_then(|memory| buf.deref(memory, 0, count));
+        match cells {
+            Some(cells) => cells
+                .iter()
+                .zip(bytes.iter())
+                .for_each(|(cell, byte)| cell.set(*byte)),
+            None => {
+                log::warn!(
+                    "{}: buffer of {} bytes at offset {} is out of bounds",
+                    import,
+                    len,
+                    buf.offset(),
+                );
+                return -1;
+            }
+        }
+    }
+
+    bytes.len() as i32
+}
+
+/// add `cuda_supported_functions` to each namespace of `externs` that
+/// doesn't have it yet, listing the names of that namespace
+pub(super) fn add_supported_functions(store: &Store, externs: &mut Vec<(String, String, Extern)>) {
+    let mut namespaces: Vec<(String, Vec<&str>)> = Vec::new();
+    for (namespace, name, _) in externs.iter() {
+        match namespaces.iter_mut().find(|(ns, _)| ns == namespace) {
+            Some((_, names)) => names.push(name),
+            None => namespaces.push((namespace.clone(), vec![name])),
+        }
+    }
+
+    let functions = namespaces
+        .into_iter()
+        .filter(|(_, names)| !names.contains(&SUPPORTED_FUNCTIONS_NAME))
+        .map(|(namespace, mut names)| {
+            names.push(SUPPORTED_FUNCTIONS_NAME);
+            let env = SupportedFunctionsEnv {
+                names: Arc::new(names.join("\n").into_bytes()),
+                memory: LazyInit::new(),
+            };
+            let function = Function::new_native_with_env(store, env, cuda_supported_functions);
+
+            (
+                namespace,
+                SUPPORTED_FUNCTIONS_NAME.to_string(),
+                Extern::from(function),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    externs.extend(functions);
+}
@@ -0,0 +1,268 @@
+//! The cuda imports combined with the WASI imports, for modules that
+//! need both. Only available with the `wasi` feature, the rest of the
+//! `cuda` module doesn't depend on WASI.
+
+use super::{
+    cuda_env_t, cuda_imports_error_t, imports_status, map_to_ordered_imports, validate_imports,
+    CudaError,
+};
+use crate::wasm_c_api::externals::wasm_extern_vec_t;
+use crate::wasm_c_api::module::wasm_module_t;
+use crate::wasm_c_api::store::wasm_store_t;
+use crate::wasm_c_api::wasi::wasi_env_t;
+use wasmer_api::{ImportObject, NamedResolver, Store};
+use wasmer_wasi::{generate_import_object_from_env, get_wasi_version};
+
+/// Like `cuda_get_imports`, but the imports are resolved against
+/// the WASI imports of `wasi_env` too.
+///
+/// Returns `CUDA_IMPORTS_OK` on success. On failure, the reason is
+/// also available through the last error API.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_wasi_get_imports(
+    store: Option<&wasm_store_t>,
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    wasi_env: Option<&wasi_env_t>,
+    imports: Option<&mut wasm_extern_vec_t>,
+) -> cuda_imports_error_t {
+    imports_status(|| {
+        cuda_wasi_get_imports_inner(store, module, cuda_env, wasi_env, imports, false)
+    })
+}
+
+/// Like `cuda_wasi_get_imports`, but only tells whether it succeeded.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_wasi_get_imports_bool(
+    store: Option<&wasm_store_t>,
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    wasi_env: Option<&wasi_env_t>,
+    imports: Option<&mut wasm_extern_vec_t>,
+) -> bool {
+    cuda_wasi_get_imports(store, module, cuda_env, wasi_env, imports)
+        == cuda_imports_error_t::CUDA_IMPORTS_OK
+}
+
+/// Like `cuda_wasi_get_imports`, but fails with
+/// `CUDA_IMPORTS_COLLISION` if a cuda import has the same module and
+/// name as a WASI import, instead of letting one of them shadow the
+/// other. The last error names the colliding imports.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_wasi_get_imports_strict(
+    store: Option<&wasm_store_t>,
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    wasi_env: Option<&wasi_env_t>,
+    imports: Option<&mut wasm_extern_vec_t>,
+) -> cuda_imports_error_t {
+    imports_status(|| cuda_wasi_get_imports_inner(store, module, cuda_env, wasi_env, imports, true))
+}
+
+fn cuda_wasi_get_imports_inner(
+    store: Option<&wasm_store_t>,
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    wasi_env: Option<&wasi_env_t>,
+    imports: Option<&mut wasm_extern_vec_t>,
+    strict: bool,
+) -> Result<(), CudaError> {
+    let store = store.ok_or(CudaError::NullArgument("store"))?;
+    let module = module.ok_or(CudaError::NullArgument("module"))?;
+    let cuda_env = cuda_env.ok_or(CudaError::NullArgument("cuda_env"))?;
+    let wasi_env = wasi_env.ok_or(CudaError::NullArgument("wasi_env"))?;
+    let imports = imports.ok_or(CudaError::NullArgument("imports"))?;
+
+    let store = &store.inner;
+
+    let import_object = cuda_wasi_import_object(store, module, cuda_env, wasi_env, strict)?;
+
+    map_to_ordered_imports(imports, module, import_object, store)
+}
+
+/// build the import object holding both the wasi imports and the cuda imports,
+/// failing on colliding imports if `strict` is set
+fn cuda_wasi_import_object(
+    store: &Store,
+    module: &wasm_module_t,
+    cuda_env: &cuda_env_t,
+    wasi_env: &wasi_env_t,
+    strict: bool,
+) -> Result<ImportObject, CudaError> {
+    let version = get_wasi_version(&module.inner, false).ok_or(CudaError::NoWasiVersion)?;
+
+    let mut import_object = generate_import_object_from_env(store, wasi_env.inner.clone(), version);
+    if strict {
+        cuda_env
+            .add_to_import_checked(store, &mut import_object)
+            .map_err(CudaError::Collision)?;
+    } else {
+        cuda_env.add_to_import(store, &mut import_object);
+    }
+
+    Ok(import_object)
+}
+
+/// Like `cuda_validate_module`, but the imports are resolved against
+/// the WASI imports of `wasi_env` too.
+///
+/// The cuda imports are still checked from their names only, but the
+/// WASI imports are built: `wasmer_wasi` doesn't expose their names
+/// otherwise.
+#[no_mangle]
+pub unsafe extern "C" fn cuda_wasi_validate_module(
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    wasi_env: Option<&wasi_env_t>,
+    resolved_imports: Option<&mut usize>,
+) -> cuda_imports_error_t {
+    imports_status(|| cuda_wasi_validate_module_inner(module, cuda_env, wasi_env, resolved_imports))
+}
+
+fn cuda_wasi_validate_module_inner(
+    module: Option<&wasm_module_t>,
+    cuda_env: Option<&cuda_env_t>,
+    wasi_env: Option<&wasi_env_t>,
+    resolved_imports: Option<&mut usize>,
+) -> Result<(), CudaError> {
+    let module = module.ok_or(CudaError::NullArgument("module"))?;
+    let cuda_env = cuda_env.ok_or(CudaError::NullArgument("cuda_env"))?;
+    let wasi_env = wasi_env.ok_or(CudaError::NullArgument("wasi_env"))?;
+
+    let store = module.inner.store();
+    let version = get_wasi_version(&module.inner, false).ok_or(CudaError::NoWasiVersion)?;
+    let wasi_import_object =
+        generate_import_object_from_env(store, wasi_env.inner.clone(), version);
+    let import_names = cuda_env.import_names(store);
+
+    validate_imports(module, resolved_imports, |namespace, name| {
+        import_names.contains(&(namespace.to_string(), name.to_string()))
+            || wasi_import_object
+                .resolve_by_name(namespace, name)
+                .is_some()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use inline_c::assert_c;
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_wasi_get_imports_error_codes() {
+        (assert_c! {
+            #include <stdlib.h>
+            #include <string.h>
+            #include "tests/wasmer.h"
+
+            static void assert_last_error(const char* expected) {
+                int error_length = wasmer_last_error_length();
+                assert(error_length > 0);
+
+                char* error_message = malloc(error_length);
+                wasmer_last_error_message(error_message, error_length);
+                assert(strstr(error_message, expected) != NULL);
+                free(error_message);
+            }
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                wasm_byte_vec_t wat;
+                wasmer_byte_vec_new_from_string(
+                    &wat,
+                    "(module\n"
+                    "  (import \"host\" \"missing\" (func)))"
+                );
+                wasm_byte_vec_t wasm;
+                wat2wasm(&wat, &wasm);
+                wasm_module_t* module = wasm_module_new(store, &wasm);
+                assert(module);
+
+                cuda_env_t* cuda_env = cuda_env_new();
+                assert(cuda_env);
+
+                wasi_config_t* config = wasi_config_new("example_program");
+                wasi_env_t* wasi_env = wasi_env_new(config);
+                assert(wasi_env);
+
+                wasm_extern_vec_t imports;
+                wasm_extern_vec_new_empty(&imports);
+
+                assert(cuda_wasi_get_imports(store, module, cuda_env, NULL, &imports) == CUDA_IMPORTS_NULL_ARG);
+                assert_last_error("`wasi_env` argument is NULL");
+
+                assert(cuda_wasi_get_imports(store, module, cuda_env, wasi_env, &imports) == CUDA_IMPORTS_NO_WASI_VERSION);
+                assert_last_error("WASI version");
+
+                assert(!cuda_wasi_get_imports_bool(store, module, cuda_env, wasi_env, &imports));
+                assert_last_error("WASI version");
+
+                size_t resolved_imports = 42;
+                assert(cuda_wasi_validate_module(module, cuda_env, wasi_env, &resolved_imports) == CUDA_IMPORTS_NO_WASI_VERSION);
+                assert_last_error("WASI version");
+
+                wasm_extern_vec_delete(&imports);
+                wasi_env_delete(wasi_env);
+                cuda_env_delete(cuda_env);
+                wasm_module_delete(module);
+                wasm_byte_vec_delete(&wasm);
+                wasm_byte_vec_delete(&wat);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+
+    #[cfg(feature = "wat")]
+    #[test]
+    fn test_cuda_wasi_get_imports_strict() {
+        (assert_c! {
+            #include "tests/wasmer.h"
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                wasm_byte_vec_t wat;
+                wasmer_byte_vec_new_from_string(
+                    &wat,
+                    "(module\n"
+                    "  (import \"wasi_snapshot_preview1\" \"proc_exit\" (func (param i32))))"
+                );
+                wasm_byte_vec_t wasm;
+                wat2wasm(&wat, &wasm);
+                wasm_module_t* module = wasm_module_new(store, &wasm);
+                assert(module);
+
+                cuda_env_t* cuda_env = cuda_env_new();
+                assert(cuda_env);
+
+                wasi_config_t* config = wasi_config_new("example_program");
+                wasi_env_t* wasi_env = wasi_env_new(config);
+                assert(wasi_env);
+
+                wasm_extern_vec_t imports;
+                wasm_extern_vec_new_empty(&imports);
+                assert(cuda_wasi_get_imports_strict(store, module, cuda_env, wasi_env, &imports) == CUDA_IMPORTS_OK);
+                assert(imports.size == 1);
+
+                wasm_extern_vec_delete(&imports);
+                wasi_env_delete(wasi_env);
+                cuda_env_delete(cuda_env);
+                wasm_module_delete(module);
+                wasm_byte_vec_delete(&wasm);
+                wasm_byte_vec_delete(&wat);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+}
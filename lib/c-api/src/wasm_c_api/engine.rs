@@ -25,7 +25,7 @@ use wasmer_engine_universal::Universal;
 /// This is a Wasmer-specific type with Wasmer-specific functions for
 /// manipulating it.
 #[cfg(feature = "compiler")]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(C)]
 pub enum wasmer_compiler_t {
     /// Variant to represent the Cranelift compiler. See the
@@ -109,6 +109,10 @@ pub struct wasm_config_t {
     pub(super) nan_canonicalization: bool,
     pub(super) features: Option<Box<wasmer_features_t>>,
     pub(super) target: Option<Box<wasmer_target_t>>,
+    /// if not empty, modules using the cuda imports can only be
+    /// compiled by one of these compilers
+    #[cfg(all(feature = "cuda", feature = "compiler"))]
+    pub(super) cuda_required_compilers: Vec<wasmer_compiler_t>,
 }
 
 /// Create a new default Wasmer configuration.
@@ -284,6 +288,20 @@ pub extern "C" fn wasm_config_set_engine(config: &mut wasm_config_t, engine: was
 #[repr(C)]
 pub struct wasm_engine_t {
     pub(crate) inner: Arc<dyn Engine + Send + Sync>,
+    /// the compiler of this engine, if it isn't allowed to compile
+    /// modules using the cuda imports
+    #[cfg(all(feature = "cuda", feature = "compiler"))]
+    pub(crate) cuda_disallowed_compiler: Option<wasmer_compiler_t>,
+}
+
+impl wasm_engine_t {
+    fn new(inner: Arc<dyn Engine + Send + Sync>) -> Self {
+        Self {
+            inner,
+            #[cfg(all(feature = "cuda", feature = "compiler"))]
+            cuda_disallowed_compiler: None,
+        }
+    }
 }
 
 #[cfg(feature = "compiler")]
@@ -317,7 +335,7 @@ cfg_if! {
         pub extern "C" fn wasm_engine_new() -> Box<wasm_engine_t> {
             let compiler_config: Box<dyn CompilerConfig> = get_default_compiler_config();
             let engine: Arc<dyn Engine + Send + Sync> = Arc::new(Universal::new(compiler_config).engine());
-            Box::new(wasm_engine_t { inner: engine })
+            Box::new(wasm_engine_t::new(engine))
         }
     } else if #[cfg(feature = "universal")] {
         /// Creates a new headless Universal engine.
@@ -330,7 +348,7 @@ cfg_if! {
         #[no_mangle]
         pub extern "C" fn wasm_engine_new() -> Box<wasm_engine_t> {
             let engine: Arc<dyn Engine + Send + Sync> = Arc::new(Universal::headless().engine());
-            Box::new(wasm_engine_t { inner: engine })
+            Box::new(wasm_engine_t::new(engine))
         }
     } else if #[cfg(all(feature = "dylib", feature = "compiler"))] {
         /// Creates a new Dylib engine with the default compiler.
@@ -344,7 +362,7 @@ cfg_if! {
         pub extern "C" fn wasm_engine_new() -> Box<wasm_engine_t> {
             let compiler_config: Box<dyn CompilerConfig> = get_default_compiler_config();
             let engine: Arc<dyn Engine + Send + Sync> = Arc::new(Dylib::new(compiler_config).engine());
-            Box::new(wasm_engine_t { inner: engine })
+            Box::new(wasm_engine_t::new(engine))
         }
     } else if #[cfg(feature = "dylib")] {
         /// Creates a new headless Dylib engine.
@@ -357,7 +375,7 @@ cfg_if! {
         #[no_mangle]
         pub extern "C" fn wasm_engine_new() -> Box<wasm_engine_t> {
             let engine: Arc<dyn Engine + Send + Sync> = Arc::new(Dylib::headless().engine());
-            Box::new(wasm_engine_t { inner: engine })
+            Box::new(wasm_engine_t::new(engine))
         }
     }
     // There are currently no uses of the Staticlib engine + compiler from the C API.
@@ -373,7 +391,7 @@ cfg_if! {
         #[no_mangle]
         pub extern "C" fn wasm_engine_new() -> Box<wasm_engine_t> {
             let engine: Arc<dyn Engine + Send + Sync> = Arc::new(Staticlib::headless().engine());
-            Box::new(wasm_engine_t { inner: engine })
+            Box::new(wasm_engine_t::new(engine))
         }
     } else {
         /// Creates a new unknown engine, i.e. it will panic with an error message.
@@ -544,7 +562,18 @@ pub extern "C" fn wasm_engine_new_with_config(
                     }
                 },
             };
-            Some(Box::new(wasm_engine_t { inner }))
+
+            #[allow(unused_mut)]
+            let mut engine = wasm_engine_t::new(inner);
+
+            #[cfg(feature = "cuda")]
+            if !config.cuda_required_compilers.is_empty()
+                && !config.cuda_required_compilers.contains(&config.compiler)
+            {
+                engine.cuda_disallowed_compiler = Some(config.compiler);
+            }
+
+            Some(Box::new(engine))
         } else {
             let inner: Arc<dyn Engine + Send + Sync> = match config.engine {
                 wasmer_engine_t::UNIVERSAL => {
@@ -605,7 +634,7 @@ pub extern "C" fn wasm_engine_new_with_config(
                     }
                 },
             };
-            Some(Box::new(wasm_engine_t { inner }))
+            Some(Box::new(wasm_engine_t::new(inner)))
         }
     }
 }
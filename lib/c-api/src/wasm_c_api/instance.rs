@@ -1,3 +1,5 @@
+#[cfg(feature = "cuda")]
+use super::cuda::cuda_env_t;
 use super::externals::wasm_extern_vec_t;
 use super::module::wasm_module_t;
 use super::store::wasm_store_t;
@@ -10,6 +12,11 @@ use wasmer_api::{Extern, Instance, InstantiationError};
 #[allow(non_camel_case_types)]
 pub struct wasm_instance_t {
     pub(crate) inner: Arc<Instance>,
+    /// The CUDA environment bound by `cuda_instance_new`, if any. It
+    /// is a reference on the caller's handle, so that
+    /// `wasm_instance_get_cuda_env` returns the same pointer.
+    #[cfg(feature = "cuda")]
+    pub(crate) cuda_env: Option<Arc<cuda_env_t>>,
 }
 
 /// Creates a new instance from a WebAssembly module and a
@@ -86,7 +93,11 @@ pub unsafe extern "C" fn wasm_instance_new(
         }
     };
 
-    Some(Box::new(wasm_instance_t { inner: instance }))
+    Some(Box::new(wasm_instance_t {
+        inner: instance,
+        #[cfg(feature = "cuda")]
+        cuda_env: None,
+    }))
 }
 
 /// Deletes an instance.
@@ -478,8 +478,8 @@ pub mod wat;
 ///     wasm_importtype_vec_delete(&import_types);
 ///
 ///     // init imports
-///     bool get_import_result = cuda_wasi_get_imports(store, module, cuda_env, wasi_env, &imports);
-///     if (!get_import_result) {
+///     cuda_imports_error_t get_import_result = cuda_wasi_get_imports(store, module, cuda_env, wasi_env, &imports);
+///     if (get_import_result != CUDA_IMPORTS_OK) {
 ///         printf("get wasi imports error\n");
 ///         print_wasmer_error();
 ///         return ERROR;
@@ -552,4 +552,5 @@ pub mod wat;
 /// #    .success();
 /// # }
 /// ```
+#[cfg(feature = "cuda")]
 pub mod cuda;
@@ -35,6 +35,11 @@ pub unsafe extern "C" fn wasm_module_new(
 
     let module = c_try!(Module::from_binary(&store.inner, bytes.as_slice()));
 
+    #[cfg(all(feature = "cuda", feature = "compiler"))]
+    if let Some(compiler) = store.cuda_disallowed_compiler {
+        c_try!(super::cuda::check_cuda_compiler(&module, compiler));
+    }
+
     Some(Box::new(wasm_module_t {
         inner: Arc::new(module),
     }))
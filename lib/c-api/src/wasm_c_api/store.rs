@@ -1,3 +1,5 @@
+#[cfg(all(feature = "cuda", feature = "compiler"))]
+use super::engine::wasmer_compiler_t;
 use super::engine::wasm_engine_t;
 use wasmer_api::Store;
 
@@ -5,6 +7,9 @@ use wasmer_api::Store;
 #[allow(non_camel_case_types)]
 pub struct wasm_store_t {
     pub(crate) inner: Store,
+    /// see `wasm_engine_t::cuda_disallowed_compiler`
+    #[cfg(all(feature = "cuda", feature = "compiler"))]
+    pub(crate) cuda_disallowed_compiler: Option<wasmer_compiler_t>,
 }
 
 /// Creates a new WebAssembly store given a specific [engine][super::engine].
@@ -19,7 +24,11 @@ pub unsafe extern "C" fn wasm_store_new(
     let engine = engine?;
     let store = Store::new(&*engine.inner);
 
-    Some(Box::new(wasm_store_t { inner: store }))
+    Some(Box::new(wasm_store_t {
+        inner: store,
+        #[cfg(all(feature = "cuda", feature = "compiler"))]
+        cuda_disallowed_compiler: engine.cuda_disallowed_compiler,
+    }))
 }
 
 /// Deletes a WebAssembly store.
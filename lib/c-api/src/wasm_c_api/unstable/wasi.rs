@@ -1,12 +1,17 @@
 //! Unstable non-standard Wasmer-specific API that contains more WASI
 //! API.
 
+#[cfg(feature = "cuda")]
+use super::super::cuda::{cuda_env_t, cuda_imports_error_t, imports_status, CudaError};
 use super::super::{
     externals::wasm_extern_t, module::wasm_module_t, store::wasm_store_t, types::wasm_name_t,
-    wasi::wasi_env_t, cuda::cuda_env_t,
+    wasi::wasi_env_t,
 };
-use wasmer_api::{Extern, imports};
-use wasmer_cuda::add_cuda_to_import;
+#[cfg(feature = "cuda")]
+use std::collections::HashSet;
+#[cfg(feature = "cuda")]
+use wasmer_api::imports;
+use wasmer_api::Extern;
 use wasmer_wasi::{generate_import_object_from_env, get_wasi_version};
 
 /// Unstable non-standard type wrapping `wasm_extern_t` with the
@@ -194,6 +199,7 @@ fn wasi_get_unordered_imports_inner(
 }
 
 /// get the unordered imports for cuda
+#[cfg(feature = "cuda")]
 #[no_mangle]
 pub unsafe extern "C" fn cuda_get_unordered_imports(
     store: Option<&wasm_store_t>,
@@ -203,6 +209,7 @@ pub unsafe extern "C" fn cuda_get_unordered_imports(
     cuda_get_unordered_imports_inner(store, cuda_env, unordered_imports).is_some()
 }
 
+#[cfg(feature = "cuda")]
 fn cuda_get_unordered_imports_inner(
     store: Option<&wasm_store_t>,
     cuda_env: Option<&cuda_env_t>,
@@ -214,7 +221,7 @@ fn cuda_get_unordered_imports_inner(
     let store = &store.inner;
 
     let mut import_object = imports! {};
-    add_cuda_to_import(store, cuda_env.inner.clone(), &mut import_object);
+    cuda_env.add_to_import(store, &mut import_object);
 
     unordered_imports.set_buffer(
         import_object.into_iter().map(
@@ -230,3 +237,124 @@ fn cuda_get_unordered_imports_inner(
 
     Some(())
 }
+
+/// Like `cuda_get_unordered_imports`, but the cuda imports are
+/// appended to `unordered_imports`, which may already hold other
+/// imports, e.g. the ones of `wasi_get_unordered_imports` or host
+/// functions of the embedder.
+///
+/// Nothing is appended if a cuda import has the same module and name
+/// as an import already in `unordered_imports`: it fails with
+/// `CUDA_IMPORTS_COLLISION`, and the last error names the colliding
+/// imports, instead of letting one of them shadow the other.
+#[cfg(feature = "cuda")]
+#[no_mangle]
+pub unsafe extern "C" fn cuda_get_unordered_imports_strict(
+    store: Option<&wasm_store_t>,
+    cuda_env: Option<&cuda_env_t>,
+    unordered_imports: &mut wasmer_named_extern_vec_t,
+) -> cuda_imports_error_t {
+    imports_status(|| cuda_get_unordered_imports_strict_inner(store, cuda_env, unordered_imports))
+}
+
+#[cfg(feature = "cuda")]
+fn cuda_get_unordered_imports_strict_inner(
+    store: Option<&wasm_store_t>,
+    cuda_env: Option<&cuda_env_t>,
+    unordered_imports: &mut wasmer_named_extern_vec_t,
+) -> Result<(), CudaError> {
+    let store = store.ok_or(CudaError::NullArgument("store"))?;
+    let cuda_env = cuda_env.ok_or(CudaError::NullArgument("cuda_env"))?;
+
+    let store = &store.inner;
+
+    let registered = unordered_imports
+        .as_slice()
+        .iter()
+        .flatten()
+        .map(|named_extern| {
+            (
+                named_extern.module.as_slice().to_vec(),
+                named_extern.name.as_slice().to_vec(),
+            )
+        })
+        .collect::<HashSet<_>>();
+
+    let mut import_object = imports! {};
+    cuda_env.add_to_import(store, &mut import_object);
+    let cuda_imports = import_object.externs_vec();
+
+    let collisions = cuda_imports
+        .iter()
+        .filter(|(module, name, _)| {
+            registered.contains(&(module.as_bytes().to_vec(), name.as_bytes().to_vec()))
+        })
+        .map(|(module, name, _)| (module.clone(), name.clone()))
+        .collect::<Vec<_>>();
+    if !collisions.is_empty() {
+        return Err(CudaError::Collision(collisions));
+    }
+
+    let mut named_externs = unordered_imports.take();
+    named_externs.extend(
+        cuda_imports
+            .into_iter()
+            .map(|(module, name, extern_inner)| {
+                Some(Box::new(wasmer_named_extern_t {
+                    module: module.into(),
+                    name: name.into(),
+                    r#extern: Box::new(extern_inner.into()),
+                }))
+            }),
+    );
+    unordered_imports.set_buffer(named_externs);
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "cuda"))]
+mod tests {
+    use inline_c::assert_c;
+
+    #[test]
+    fn test_cuda_get_unordered_imports_strict() {
+        (assert_c! {
+            #include <stdlib.h>
+            #include <string.h>
+            #include "tests/wasmer.h"
+
+            int main() {
+                wasm_engine_t* engine = wasm_engine_new();
+                wasm_store_t* store = wasm_store_new(engine);
+
+                cuda_env_t* cuda_env = cuda_env_new();
+                assert(cuda_env);
+
+                wasmer_named_extern_vec_t imports;
+                wasmer_named_extern_vec_new_empty(&imports);
+                assert(cuda_get_unordered_imports_strict(store, cuda_env, &imports) == CUDA_IMPORTS_OK);
+                size_t cuda_imports_size = imports.size;
+                assert(cuda_imports_size > 0);
+
+                // every cuda import is already registered
+                assert(cuda_get_unordered_imports_strict(store, cuda_env, &imports) == CUDA_IMPORTS_COLLISION);
+                assert(imports.size == cuda_imports_size);
+
+                int error_length = wasmer_last_error_length();
+                assert(error_length > 0);
+                char* error_message = malloc(error_length);
+                wasmer_last_error_message(error_message, error_length);
+                assert(strstr(error_message, "collide") != NULL);
+                free(error_message);
+
+                wasmer_named_extern_vec_delete(&imports);
+                cuda_env_delete(cuda_env);
+                wasm_store_delete(store);
+                wasm_engine_delete(engine);
+
+                return 0;
+            }
+        })
+        .success();
+    }
+}